@@ -0,0 +1,100 @@
+use crate::{asset_path, AppState, Game, TowerQuality};
+use bevy::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// A chance level, `cost` is the gold paid to reach it and `odds` are the percents of each quality.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChanceTier {
+	pub cost: u32,
+	pub odds: BTreeMap<TowerQuality, u32>,
+}
+
+/// The chance levels in order, the game starts at the first one.
+pub struct QualityChances(Vec<ChanceTier>);
+
+impl QualityChances {
+	pub fn load(path: &Path) -> Result<Self, String> {
+		let content = fs::read_to_string(path).map_err(|error| error.to_string())?;
+		let tiers: Vec<ChanceTier> = ron::from_str(&content).map_err(|error| error.to_string())?;
+		if tiers.is_empty() {
+			return Err("there must be at least one chance level".to_string());
+		}
+		for (index, tier) in tiers.iter().enumerate() {
+			let total: u32 = tier.odds.values().sum();
+			if total != 100 {
+				return Err(format!(
+					"level {}: the odds must add up to 100, got {}",
+					index, total
+				));
+			}
+		}
+		Ok(Self(tiers))
+	}
+
+	pub fn get(&self, tier: u8) -> Option<&ChanceTier> {
+		self.0.get(tier as usize)
+	}
+
+	pub fn roll(&self, tier: u8, rng: &mut impl Rng) -> TowerQuality {
+		let odds = &self.0[tier as usize].odds;
+		let mut roll = rng.gen_range(0..100);
+		for (quality, chance) in odds.iter() {
+			if roll < *chance {
+				return *quality;
+			}
+			roll -= chance;
+		}
+		unreachable!("The odds add up to 100.")
+	}
+}
+
+/// Asks to raise the chance level, sent by the keyboard or the replay.
+pub struct UpgradeChanceEvent;
+
+pub struct ChanceUpgradedEvent;
+
+fn upgrade_chance_event_handler(
+	mut upgrade_chance: EventReader<UpgradeChanceEvent>,
+	chances: Res<QualityChances>,
+	mut game: ResMut<Game>,
+	mut chance_upgraded: EventWriter<ChanceUpgradedEvent>,
+) {
+	for _ in upgrade_chance.iter() {
+		let next = match chances.get(game.chance_tier + 1) {
+			Some(next) => next,
+			None => continue,
+		};
+		if !game.spend_gold(next.cost) {
+			continue;
+		}
+
+		game.chance_tier += 1;
+		chance_upgraded.send(ChanceUpgradedEvent);
+	}
+}
+
+pub struct ChancesPlugin;
+
+impl Plugin for ChancesPlugin {
+	fn build(&self, app: &mut App) {
+		let path = asset_path("chances.ron");
+		let chances = QualityChances::load(&path)
+			.unwrap_or_else(|error| panic!("Invalid chances {}: {}", path.display(), error));
+
+		app.insert_resource(chances)
+			.add_event::<UpgradeChanceEvent>()
+			.add_event::<ChanceUpgradedEvent>()
+			.add_system_set(
+				SystemSet::on_update(AppState::Build).with_system(
+					upgrade_chance_event_handler
+						.label("upgrade_chance")
+						.before("place_rock"),
+				),
+			);
+	}
+}
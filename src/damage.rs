@@ -0,0 +1,91 @@
+use crate::asset_path;
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AttackType {
+	Normal,
+	Pierce,
+	Siege,
+	Magic,
+	Chaos,
+}
+
+impl AttackType {
+	const ALL: [Self; 5] = [
+		Self::Normal,
+		Self::Pierce,
+		Self::Siege,
+		Self::Magic,
+		Self::Chaos,
+	];
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ArmorType {
+	Unarmored,
+	Light,
+	Medium,
+	Heavy,
+	Fortified,
+}
+
+impl ArmorType {
+	const ALL: [Self; 5] = [
+		Self::Unarmored,
+		Self::Light,
+		Self::Medium,
+		Self::Heavy,
+		Self::Fortified,
+	];
+}
+
+/// The damage multiplier of each attack type against each armor type, read from
+/// `assets/damage.ron`. Applied to the projectile hits, pure damage and poison ignore it.
+pub struct DamageMatrix(HashMap<AttackType, HashMap<ArmorType, f32>>);
+
+impl DamageMatrix {
+	pub fn load(path: &Path) -> Result<Self, String> {
+		let content = fs::read_to_string(path).map_err(|error| error.to_string())?;
+		let matrix: HashMap<AttackType, HashMap<ArmorType, f32>> =
+			ron::from_str(&content).map_err(|error| error.to_string())?;
+
+		for attack in AttackType::ALL {
+			let row = matrix
+				.get(&attack)
+				.ok_or_else(|| format!("{:?} is not defined", attack))?;
+			for armor in ArmorType::ALL {
+				match row.get(&armor) {
+					Some(multiplier) if multiplier.is_finite() && *multiplier >= 0.0 => {}
+					Some(multiplier) => {
+						return Err(format!(
+							"{:?} against {:?} can not be negative, got {}",
+							attack, armor, multiplier
+						))
+					}
+					None => return Err(format!("{:?} against {:?} is not defined", attack, armor)),
+				}
+			}
+		}
+		Ok(Self(matrix))
+	}
+
+	pub fn multiplier(&self, attack: AttackType, armor: ArmorType) -> f32 {
+		self.0[&attack][&armor]
+	}
+}
+
+pub struct DamagePlugin;
+
+impl Plugin for DamagePlugin {
+	fn build(&self, app: &mut App) {
+		let path = asset_path("damage.ron");
+		let matrix = DamageMatrix::load(&path)
+			.unwrap_or_else(|error| panic!("Invalid damage matrix {}: {}", path.display(), error));
+
+		app.insert_resource(matrix);
+	}
+}
@@ -1,17 +1,113 @@
 use crate::{
-	fit_to_grid, position_to_transform, position_to_translation, vec2_to_position, AppState, Game,
-	Graph, SlimeModifier, UpdateGameTooltipEvent,
+	asset_path, enemies_phase, AppState, ArmorType, Game, Graph, GridGeometry, Map,
+	SimulationClock, SimulationLabel, SimulationStage, StatusEffects, StatusKind,
+	UpdateGameTooltipEvent,
 };
-use bevy::core::FixedTimestep;
 use bevy::prelude::*;
-use bevy::utils::Duration;
-use std::collections::HashMap;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
 
-const ENEMY_DELAY: f64 = 1.0;
-const POISON_DELAY: f64 = 1.0;
+const ENEMY_DELAY: f32 = 1.0;
+const POISON_DELAY: f32 = 1.0;
 
-#[derive(Component)]
-pub struct SlimeUI;
+/// The enemies of one level.
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WaveDefinition {
+	pub count: usize,
+	/// Seconds between two spawns.
+	pub spawn_interval: f32,
+	pub life: usize,
+	pub armor: f32,
+	pub armor_type: ArmorType,
+	pub magic_resistance: f32,
+	pub speed: f32,
+	pub sprite: String,
+	/// Gold dropped by each slime killed.
+	pub bounty: u32,
+	/// Gold earned when the wave ends.
+	pub bonus: u32,
+}
+
+impl WaveDefinition {
+	/// Used for the levels past the end of the waves file.
+	fn from_level(level: u8) -> Self {
+		Self {
+			count: 5,
+			spawn_interval: ENEMY_DELAY,
+			life: 3 + level as usize * 5,
+			armor: 0.0 + level as f32,
+			armor_type: match level % 4 {
+				0 => ArmorType::Unarmored,
+				1 => ArmorType::Light,
+				2 => ArmorType::Medium,
+				_ => ArmorType::Heavy,
+			},
+			magic_resistance: 0.20 + (0.05 * level as f32),
+			speed: 100.0 + (5.0 * level as f32),
+			sprite: "slime.png".to_string(),
+			bounty: 2 + level as u32 / 5,
+			bonus: 10 + 2 * level as u32,
+		}
+	}
+
+	fn validate(&self) -> Result<(), String> {
+		if self.count == 0 {
+			return Err("count must be at least 1".to_string());
+		}
+		if !(self.spawn_interval.is_finite() && self.spawn_interval > 0.0) {
+			return Err(format!(
+				"spawn_interval must be a positive number of seconds, got {}",
+				self.spawn_interval
+			));
+		}
+		if self.life == 0 {
+			return Err("life must be at least 1".to_string());
+		}
+		if !self.armor.is_finite() {
+			return Err(format!("armor must be a number, got {}", self.armor));
+		}
+		if !(self.magic_resistance.is_finite() && self.magic_resistance <= 1.0) {
+			return Err(format!(
+				"magic_resistance can not be more than 1, got {}",
+				self.magic_resistance
+			));
+		}
+		if !(self.speed.is_finite() && self.speed > 0.0) {
+			return Err(format!("speed must be positive, got {}", self.speed));
+		}
+		if !asset_path(&self.sprite).is_file() {
+			return Err(format!("sprite {} is not in the assets", self.sprite));
+		}
+		Ok(())
+	}
+}
+
+/// The waves of each level in order, starting at level 0.
+pub struct Waves(Vec<WaveDefinition>);
+
+impl Waves {
+	pub fn load(path: &Path) -> Result<Self, String> {
+		let content = fs::read_to_string(path).map_err(|error| error.to_string())?;
+		let waves: Vec<WaveDefinition> =
+			ron::from_str(&content).map_err(|error| error.to_string())?;
+		for (level, wave) in waves.iter().enumerate() {
+			wave.validate()
+				.map_err(|error| format!("level {}: {}", level, error))?;
+		}
+		Ok(Self(waves))
+	}
+
+	pub fn get(&self, level: u8) -> WaveDefinition {
+		self.0
+			.get(level as usize)
+			.cloned()
+			.unwrap_or_else(|| WaveDefinition::from_level(level))
+	}
+}
+
+struct CurrentWave(WaveDefinition);
 
 #[derive(Component, Clone)]
 pub struct Slime {
@@ -19,51 +115,53 @@ pub struct Slime {
 	pub target: (usize, usize),
 	pub position_index: usize,
 	pub velocity: Vec2,
-	pub speed: f32, // TODO set base movespeed so we can reduce it with modifiers.
+	pub speed: f32,
 	pub life: usize,
 	pub max_life: usize,
 	pub armor: f32,
+	pub armor_type: ArmorType,
 	pub magic_resistance: f32,
-	pub modifiers: SlimeModifier,
-	pub ui: Entity,
+	pub effects: StatusEffects,
 	pub rank: usize,
+	pub sprite: String,
+	pub bounty: u32,
+	/// The tower that dealt the last damage, credited with the kill.
+	pub last_hit: Option<Entity>,
+	/// Reached the end of the path, it is removed without a kill.
+	pub escaped: bool,
 }
 
 impl Slime {
 	fn get_armor(&self) -> f32 {
-		let mut armor = self.armor;
-		for (modifier, _) in self.modifiers.armor.iter() {
-			armor += *modifier as f32;
-		}
-		armor
+		self.armor + self.effects.magnitude(StatusKind::Armor)
 	}
 
-	pub fn take_pure_damage(&mut self, damage: usize) {
-		if damage > self.life {
-			self.life = 0;
-		} else {
-			self.life -= damage;
+	/// Returns the life actually taken, never more than what was left.
+	pub fn take_pure_damage(&mut self, damage: usize, source: Entity) -> usize {
+		if self.life == 0 || damage == 0 {
+			return 0;
 		}
+		self.last_hit = Some(source);
+		let dealt = damage.min(self.life);
+		self.life -= dealt;
+		dealt
 	}
 
-	pub fn take_magic_damage(&mut self, damage: f32) {
+	pub fn take_magic_damage(&mut self, damage: f32, source: Entity) -> usize {
 		let computed_damage = damage * (1.0 - self.magic_resistance);
 		// iter on magic res mods
-		self.take_pure_damage(computed_damage as usize);
+		self.take_pure_damage(computed_damage as usize, source)
 	}
 
-	pub fn take_physical_damage(&mut self, damage: f32) {
+	pub fn take_physical_damage(&mut self, damage: f32, source: Entity) -> usize {
 		let computed_damage = (damage
 			* (1.0 - (0.052 * self.get_armor()) / (0.9 + 0.048 * self.get_armor().abs())))
 			as usize;
-		self.take_pure_damage(computed_damage as usize);
+		self.take_pure_damage(computed_damage as usize, source)
 	}
 
 	pub fn get_speed_vector(&self) -> Vec2 {
-		let mut speed = 100.0;
-		for (modifier, _) in self.modifiers.speed.iter() {
-			speed *= ((100 - *modifier) as f32) / 100.0;
-		}
+		let mut speed = self.speed * (100.0 - self.effects.magnitude(StatusKind::Slow)) / 100.0;
 		if speed < 1.0 {
 			speed = 1.0;
 		} else if speed > 540.0 {
@@ -72,21 +170,24 @@ impl Slime {
 		Vec2::new(speed, speed)
 	}
 
-	fn from_level(level: u8, start: (usize, usize), ui: Entity, rank: usize) -> Self {
-		let life = 3 + level as usize * 5;
+	fn from_wave(wave: &WaveDefinition, start: (usize, usize), rank: usize) -> Self {
 		Self {
 			position: start,
 			target: start,
 			position_index: 0,
 			velocity: Vec2::new(0.0, 0.0),
-			life,
-			max_life: life,
-			armor: 0.0 + level as f32,
-			magic_resistance: 0.20 + (0.05 * level as f32),
-			speed: 100.0 + (5.0 * level as f32),
-			modifiers: SlimeModifier::default(),
-			ui,
+			life: wave.life,
+			max_life: wave.life,
+			armor: wave.armor,
+			armor_type: wave.armor_type,
+			magic_resistance: wave.magic_resistance,
+			speed: wave.speed,
+			effects: StatusEffects::default(),
 			rank,
+			sprite: wave.sprite.clone(),
+			bounty: wave.bounty,
+			last_hit: None,
+			escaped: false,
 		}
 	}
 }
@@ -96,50 +197,51 @@ struct SlimeCounter {
 	total_spawned: usize,
 }
 
-fn offset_ui_translation(mut translation: Vec3) -> Vec3 {
-	translation.z = 2.0;
-	translation.y += 20.0;
-	translation
+struct EnemiesTimers {
+	spawn: Timer,
+	poison: Timer,
+}
+
+impl EnemiesTimers {
+	fn new(spawn_interval: f32) -> Self {
+		Self {
+			spawn: Timer::from_seconds(spawn_interval, true),
+			poison: Timer::from_seconds(POISON_DELAY, true),
+		}
+	}
 }
 
+fn start_wave(
+	waves: Res<Waves>,
+	game: Res<Game>,
+	mut current_wave: ResMut<CurrentWave>,
+	mut timers: ResMut<EnemiesTimers>,
+) {
+	current_wave.0 = waves.get(game.level);
+	*timers = EnemiesTimers::new(current_wave.0.spawn_interval);
+}
+
+#[allow(clippy::too_many_arguments)]
 fn begin_wave(
 	mut commands: Commands,
-	asset_server: Res<AssetServer>,
+	clock: Res<SimulationClock>,
+	mut timers: ResMut<EnemiesTimers>,
 	mut slime_counter: ResMut<SlimeCounter>,
+	current_wave: Res<CurrentWave>,
 	graph: Res<Graph>,
+	geometry: Res<GridGeometry>,
 	game: Res<Game>,
-	app_state: Res<State<AppState>>,
 	mut update_ui: EventWriter<UpdateGameTooltipEvent>,
 ) {
-	if *app_state.current() == AppState::Enemies && slime_counter.total_spawned < 5 {
+	let spawn_ready = timers.spawn.tick(clock.step).just_finished();
+	if (spawn_ready || slime_counter.total_spawned == 0)
+		&& slime_counter.total_spawned < current_wave.0.count
+	{
 		let start = graph.get_node_position(graph.start).unwrap();
-		let transform = position_to_transform(start.0 as f32, start.1 as f32)
-			.with_scale(Vec3::new(3.0, 3.0, 1.0));
-
-		let entity_ui = commands
-			.spawn_bundle(SpriteBundle {
-				sprite: Sprite {
-					color: Color::rgb(0.1, 0.9, 0.1),
-					custom_size: Some(Vec2::new(30.0, 1.0)),
-					..Default::default()
-				},
-				transform: Transform::from_translation(offset_ui_translation(
-					transform.translation,
-				)),
-				..Default::default()
-			})
-			.insert(SlimeUI)
-			.id();
-
-		let slime = Slime::from_level(game.level, start, entity_ui, slime_counter.total_spawned);
-
-		commands
-			.spawn_bundle(SpriteBundle {
-				texture: asset_server.load("slime.png"),
-				transform,
-				..Default::default()
-			})
-			.insert(slime.clone());
+		let transform = geometry.position_to_transform(start.0 as f32, start.1 as f32);
+		let slime = Slime::from_wave(&current_wave.0, start, slime_counter.total_spawned);
+
+		commands.spawn().insert(transform).insert(slime.clone());
 
 		if slime_counter.total_spawned == 0 {
 			update_ui.send(UpdateGameTooltipEvent {
@@ -157,7 +259,9 @@ fn begin_wave(
 fn slime_pathfinding(
 	mut update_ui: EventWriter<UpdateGameTooltipEvent>,
 	graph: Res<Graph>,
+	geometry: Res<GridGeometry>,
 	mut game: ResMut<Game>,
+	mut clock: ResMut<SimulationClock>,
 	mut slimes: Query<&mut Slime>,
 	mut app_state: ResMut<State<AppState>>,
 ) {
@@ -166,8 +270,10 @@ fn slime_pathfinding(
 			if let Some(target) = graph.next_step(slime.position_index) {
 				slime.target = target;
 				slime.position_index += 1;
-				let target_vector = position_to_translation(target.0 as f32, target.1 as f32)
-					- position_to_translation(slime.position.0 as f32, slime.position.1 as f32);
+				let target_vector = geometry
+					.position_to_translation(target.0 as f32, target.1 as f32)
+					- geometry
+						.position_to_translation(slime.position.0 as f32, slime.position.1 as f32);
 				slime.velocity = Vec2::new(target_vector.x, target_vector.y).normalize()
 					* slime.get_speed_vector();
 			} else if slime.life > 0 {
@@ -175,10 +281,13 @@ fn slime_pathfinding(
 
 				if game.lives == 0 {
 					app_state.set(AppState::GameOver).unwrap();
+					clock.hold();
 					return;
 				}
 
+				// Removed like a dead slime, but it drops nothing.
 				slime.life = 0;
+				slime.escaped = true;
 				update_ui.send(UpdateGameTooltipEvent {
 					slime: None,
 					level: game.level,
@@ -189,13 +298,12 @@ fn slime_pathfinding(
 	}
 }
 
-fn update_slime_position(mut slimes: Query<(&Transform, &mut Slime)>) {
+fn update_slime_position(geometry: Res<GridGeometry>, mut slimes: Query<(&Transform, &mut Slime)>) {
 	for (transform, mut slime) in slimes.iter_mut() {
 		// TODO because of fit to grid the slimes aren't in the middle of the cells.
-		if let Some(position) = fit_to_grid(vec2_to_position(Vec2::new(
-			transform.translation.x,
-			transform.translation.y,
-		))) {
+		if let Some(position) = geometry.fit_to_grid(
+			geometry.vec2_to_position(Vec2::new(transform.translation.x, transform.translation.y)),
+		) {
 			if slime.target == position {
 				slime.position = position;
 			}
@@ -203,98 +311,123 @@ fn update_slime_position(mut slimes: Query<(&Transform, &mut Slime)>) {
 	}
 }
 
-fn slime_movement(
-	time: Res<Time>,
-	mut slimes: Query<(&mut Transform, &Slime), Without<SlimeUI>>,
-	mut uis: Query<&mut Transform, With<SlimeUI>>,
-) {
+fn slime_movement(clock: Res<SimulationClock>, mut slimes: Query<(&mut Transform, &Slime)>) {
 	for (mut transform, slime) in slimes.iter_mut() {
-		transform.translation.x += slime.velocity.x * time.delta_seconds();
-		transform.translation.y += slime.velocity.y * time.delta_seconds();
-		let mut ui = uis.get_mut(slime.ui).unwrap();
-		ui.translation = offset_ui_translation(transform.translation);
+		transform.translation.x += slime.velocity.x * clock.delta_seconds();
+		transform.translation.y += slime.velocity.y * clock.delta_seconds();
 	}
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DamageKind {
+	Physical,
+	Magic,
+	Pure,
+}
+
+/// Sent for each hit that took life from a slime.
+pub struct DamageEvent {
+	pub tower: Entity,
+	pub kind: DamageKind,
+	pub amount: usize,
+	pub critical: bool,
+}
+
+/// Sent for each slime killed by a tower.
+pub struct SlimeKilledEvent {
+	pub tower: Entity,
+}
+
 fn slime_death(
 	mut commands: Commands,
 	slimes: Query<(Entity, &Slime)>,
 	mut slime_counter: ResMut<SlimeCounter>,
+	mut game: ResMut<Game>,
+	mut slime_killed: EventWriter<SlimeKilledEvent>,
 ) {
 	for (entity, slime) in slimes.iter() {
 		if slime.life == 0 {
-			commands.entity(slime.ui).despawn_recursive();
 			commands.entity(entity).despawn_recursive();
 			slime_counter.count -= 1;
+			if slime.escaped {
+				continue;
+			}
+			game.earn_gold(slime.bounty);
+			if let Some(tower) = slime.last_hit {
+				slime_killed.send(SlimeKilledEvent { tower });
+			}
 		}
 	}
 }
 
-fn update_slime_life_ui(
-	slimes: Query<&Slime, Without<SlimeUI>>,
-	mut uis: Query<&mut Transform, With<SlimeUI>>,
-) {
-	for slime in slimes.iter() {
-		uis.get_mut(slime.ui).unwrap().scale =
-			Vec3::new(slime.life as f32 / slime.max_life as f32, 1.0, 1.0);
-	}
-}
-
 fn end_enemies_state(
 	mut slime_counter: ResMut<SlimeCounter>,
+	current_wave: Res<CurrentWave>,
 	mut app_state: ResMut<State<AppState>>,
+	mut clock: ResMut<SimulationClock>,
 	mut game: ResMut<Game>,
 ) {
-	if slime_counter.total_spawned == 5 && slime_counter.count == 0 {
+	if slime_counter.total_spawned == current_wave.0.count && slime_counter.count == 0 {
+		game.earn_gold(current_wave.0.bonus);
 		game.rocks_count = 0;
 		game.level += 1;
 		app_state.set(AppState::Build).unwrap();
+		clock.hold();
 		slime_counter.total_spawned = 0;
 	}
 }
 
-fn filter_timers(x: HashMap<i32, Duration>, delta: Duration) -> HashMap<i32, Duration> {
-	x.into_iter()
-		.filter(|&(_, d)| d > delta) // remove mods about to expire
-		.map(|(v, d)| (v, d - delta)) // reduce duration for the others
-		.collect()
-}
-
-fn update_timed_modifiers(time: Res<Time>, mut query: Query<&mut Slime>) {
+fn update_timed_modifiers(clock: Res<SimulationClock>, mut query: Query<&mut Slime>) {
 	for mut slime in query.iter_mut() {
-		slime.modifiers.armor = filter_timers(slime.modifiers.armor.clone(), time.delta());
-		slime.modifiers.poison = filter_timers(slime.modifiers.poison.clone(), time.delta());
-		slime.modifiers.speed = filter_timers(slime.modifiers.speed.clone(), time.delta());
+		slime.effects.tick(clock.step);
 	}
 }
 
-fn update_slime_modifier_ui(mut query: Query<(&mut Sprite, &Slime)>) {
-	for (mut sprite, slime) in query.iter_mut() {
-		let mut mask = Color::rgb(1.0, 1.0, 1.0);
-		if !slime.modifiers.poison.is_empty() {
-			mask.set_r(mask.r() * 0.2);
-			mask.set_b(mask.b() * 0.2);
-		}
-		if !slime.modifiers.speed.is_empty() {
-			mask.set_r(mask.r() * 0.2);
-			mask.set_g(mask.g() * 0.2);
-		}
-		if !slime.modifiers.armor.is_empty() {
-			mask.set_g(mask.g() * 0.2);
-			mask.set_b(mask.b() * 0.2);
-		}
-		sprite.color = mask;
+fn take_poison_damage(
+	clock: Res<SimulationClock>,
+	mut timers: ResMut<EnemiesTimers>,
+	mut query: Query<&mut Slime>,
+	mut damage_dealt: EventWriter<DamageEvent>,
+) {
+	if !timers.poison.tick(clock.step).just_finished() {
+		return;
 	}
-}
 
-fn take_poison_damage(mut query: Query<&mut Slime>) {
 	for mut slime in query.iter_mut() {
-		let mut damage = 0.0;
-		for (value, _) in slime.modifiers.poison.iter_mut() {
-			damage += *value as f32;
+		let sources: Vec<(Entity, f32)> = slime
+			.effects
+			.iter(StatusKind::Poison)
+			.map(|effect| (effect.source, effect.magnitude))
+			.collect();
+		let total: f32 = sources.iter().map(|(_, magnitude)| magnitude).sum();
+		if total <= 0.0 {
+			continue;
 		}
-		if damage > 0.0 {
-			slime.take_magic_damage(damage);
+		// The capped total is resisted once, the strongest poison is credited with the kill.
+		let mut strongest = sources[0];
+		for &source in &sources {
+			if source.1 > strongest.1 {
+				strongest = source;
+			}
+		}
+		let capped = slime.effects.magnitude(StatusKind::Poison);
+		let dealt = slime.take_magic_damage(capped, strongest.0);
+
+		// Each tower is credited with its share of the damage, the shares add up to `dealt`.
+		let mut applied = 0.0;
+		let mut credited = 0;
+		for (tower, magnitude) in sources {
+			applied += magnitude;
+			let amount = (dealt as f32 * applied / total).round() as usize - credited;
+			credited += amount;
+			if amount > 0 {
+				damage_dealt.send(DamageEvent {
+					tower,
+					kind: DamageKind::Magic,
+					amount,
+					critical: false,
+				});
+			}
 		}
 	}
 }
@@ -303,30 +436,65 @@ pub struct EnemiesPlugin;
 
 impl Plugin for EnemiesPlugin {
 	fn build(&self, app: &mut App) {
+		let waves_file = app
+			.world
+			.get_resource::<Map>()
+			.and_then(|map| map.waves.clone())
+			.unwrap_or_else(|| "waves.ron".to_string());
+		let path = asset_path(&waves_file);
+		let waves = Waves::load(&path)
+			.unwrap_or_else(|error| panic!("Invalid waves {}: {}", path.display(), error));
+
 		app.insert_resource(SlimeCounter {
 			count: 0,
 			total_spawned: 0,
 		})
-		.add_system_set(
-			SystemSet::new()
-				.with_run_criteria(FixedTimestep::step(ENEMY_DELAY))
-				.with_system(begin_wave),
-		)
-		.add_system_set(
+		.insert_resource(CurrentWave(waves.get(0)))
+		.insert_resource(waves)
+		.insert_resource(EnemiesTimers::new(ENEMY_DELAY))
+		.add_event::<SlimeKilledEvent>()
+		.add_event::<DamageEvent>()
+		.add_system_set(SystemSet::on_enter(AppState::Enemies).with_system(start_wave))
+		.add_system_set_to_stage(
+			SimulationStage,
 			SystemSet::new()
-				.with_run_criteria(FixedTimestep::step(POISON_DELAY))
-				.with_system(take_poison_damage),
-		)
-		.add_system_set(
-			SystemSet::on_update(AppState::Enemies)
-				.with_system(slime_pathfinding)
-				.with_system(slime_movement)
-				.with_system(slime_death)
-				.with_system(end_enemies_state)
-				.with_system(update_timed_modifiers)
-				.with_system(update_slime_life_ui)
-				.with_system(update_slime_modifier_ui)
-				.with_system(update_slime_position),
+				.with_run_criteria(enemies_phase)
+				.with_system(begin_wave.label(SimulationLabel::Spawn))
+				.with_system(
+					update_timed_modifiers
+						.label(SimulationLabel::Modifiers)
+						.after(SimulationLabel::Spawn),
+				)
+				.with_system(
+					take_poison_damage
+						.label(SimulationLabel::Poison)
+						.after(SimulationLabel::Modifiers),
+				)
+				.with_system(
+					update_slime_position
+						.label(SimulationLabel::Position)
+						.after(SimulationLabel::Poison),
+				)
+				.with_system(
+					slime_pathfinding
+						.label(SimulationLabel::Pathfinding)
+						.after(SimulationLabel::Position),
+				)
+				.with_system(
+					slime_movement
+						.label(SimulationLabel::Movement)
+						.after(SimulationLabel::Pathfinding),
+				)
+				.with_system(
+					slime_death
+						.label(SimulationLabel::Death)
+						.after(SimulationLabel::Projectiles),
+				)
+				.with_system(
+					end_enemies_state
+						.label(SimulationLabel::EndWave)
+						.after(SimulationLabel::Death),
+				),
 		);
 	}
 }
@@ -1,6 +1,12 @@
-use crate::{position_to_transform, AppState, Graph, GRID_SIZE, TILE_SIZE};
+use crate::{
+	AppState, Graph, GridGeometry, Map, NewPathEvent, SaveGame, TemporaryTower, Tower, TowerKind,
+	TowerQuality, UpdateGameTooltipEvent, UpdateTowerTooltipEvent,
+};
 use bevy::prelude::*;
 
+/// Gold paid to remove a rock.
+pub const REMOVE_ROCK_COST: u32 = 10;
+
 #[derive(Component)]
 pub struct Tile;
 
@@ -12,11 +18,8 @@ pub enum CellContent {
 	End,
 	Rock,
 	Tower(Entity),
-	CheckPoint1,
-	CheckPoint2,
-	CheckPoint3,
-	CheckPoint4,
-	CheckPoint5,
+	/// Index in the ordered checkpoints of the map.
+	CheckPoint(usize),
 }
 
 #[derive(Component)]
@@ -32,35 +35,51 @@ pub struct Game {
 	pub rocks_count: u8,
 	pub lives: u8,
 	pub level: u8,
+	/// Index in the `QualityChances` used to roll the quality of new gems.
+	pub chance_tier: u8,
+	pub gold: u32,
+}
+
+impl Game {
+	pub fn earn_gold(&mut self, amount: u32) {
+		self.gold = self.gold.saturating_add(amount);
+	}
+
+	/// Nothing is spent and `false` is returned when there is not enough gold.
+	pub fn spend_gold(&mut self, amount: u32) -> bool {
+		if self.gold < amount {
+			return false;
+		}
+		self.gold -= amount;
+		true
+	}
 }
 
-fn init_game(mut commands: Commands, mut game: ResMut<Game>, mut graph: ResMut<Graph>) {
+/// The rocks placed in the current Build phase, the most recent last.
+#[derive(Default)]
+pub struct BuildHistory {
+	rocks: Vec<((usize, usize), CellContent)>,
+	/// Gems of the undone rocks, given again to the next rocks so an undo never rerolls.
+	pub pending_rolls: Vec<(TowerKind, TowerQuality)>,
+}
+
+fn init_game(
+	mut commands: Commands,
+	mut game: ResMut<Game>,
+	mut graph: ResMut<Graph>,
+	map: Res<Map>,
+	geometry: Res<GridGeometry>,
+	mut new_path: EventWriter<NewPathEvent>,
+) {
 	game.lives = 10;
 	game.level = 0;
 
-	let size = GRID_SIZE as usize;
-	for y in 0..size {
+	graph.checkpoints = vec![0; map.checkpoints.len()];
+	for y in 0..map.height {
 		game.grid.push(vec![]);
-		for x in 0..size {
-			let (content, color, walkable) = if x == 0 || y == 0 || x == size - 1 || y == size - 1 {
-				(CellContent::Limit, Color::WHITE, false)
-			} else if x == 2 && y == 2 {
-				(CellContent::Start, Color::GREEN, true)
-			} else if x == size - 3 && y == size - 3 {
-				(CellContent::End, Color::RED, true)
-			} else if x == 2 && y == ((size - 1) / 2) {
-				(CellContent::CheckPoint1, Color::BLUE, true)
-			} else if x == size - 3 && y == ((size - 1) / 2) {
-				(CellContent::CheckPoint2, Color::BLUE, true)
-			} else if x == size - 3 && y == 2 {
-				(CellContent::CheckPoint3, Color::BLUE, true)
-			} else if x == ((size - 1) / 2) && y == 2 {
-				(CellContent::CheckPoint4, Color::BLUE, true)
-			} else if x == ((size - 1) / 2) && y == size - 3 {
-				(CellContent::CheckPoint5, Color::BLUE, true)
-			} else {
-				(CellContent::Empty, Color::BLACK, true)
-			};
+		for x in 0..map.width {
+			let content = map.content((x, y));
+			let walkable = !matches!(content, CellContent::Limit | CellContent::Rock);
 
 			let node_id = graph.add(walkable, x, y);
 
@@ -71,15 +90,8 @@ fn init_game(mut commands: Commands, mut game: ResMut<Game>, mut graph: ResMut<G
 			};
 
 			let entity = commands
-				.spawn_bundle(SpriteBundle {
-					sprite: Sprite {
-						color,
-						custom_size: Some(Vec2::new(TILE_SIZE, TILE_SIZE)),
-						..Default::default()
-					},
-					transform: position_to_transform(x as f32, y as f32),
-					..Default::default()
-				})
+				.spawn()
+				.insert(geometry.position_to_transform(x as f32, y as f32))
 				.insert(cell)
 				.id();
 			game.grid[y].push(entity);
@@ -87,31 +99,147 @@ fn init_game(mut commands: Commands, mut game: ResMut<Game>, mut graph: ResMut<G
 			match content {
 				CellContent::Start => graph.start = node_id,
 				CellContent::End => graph.end = node_id,
-				CellContent::CheckPoint1 => graph.checkpoint_1 = node_id,
-				CellContent::CheckPoint2 => graph.checkpoint_2 = node_id,
-				CellContent::CheckPoint3 => graph.checkpoint_3 = node_id,
-				CellContent::CheckPoint4 => graph.checkpoint_4 = node_id,
-				CellContent::CheckPoint5 => graph.checkpoint_5 = node_id,
+				CellContent::CheckPoint(index) => graph.checkpoints[index] = node_id,
 				_ => {}
 			}
 		}
 	}
+
+	if graph.bfs() {
+		new_path.send(NewPathEvent(graph.path.to_vec()));
+	} else {
+		panic!("The map has no path from start to end.");
+	}
+}
+
+fn place_rock_event_handler(
+	mut place_rock: EventReader<PlaceRockEvent>,
+	game: Res<Game>,
+	mut history: ResMut<BuildHistory>,
+	mut graph: ResMut<Graph>,
+	mut cells: Query<(Entity, &mut Cell)>,
+	mut new_path: EventWriter<NewPathEvent>,
+	mut rock_placed: EventWriter<RockPlacedEvent>,
+) {
+	let mut placed = 0;
+	for event in place_rock.iter() {
+		if game.rocks_count + placed >= 5 {
+			break;
+		}
+
+		let (x, y) = event.position;
+		if let Ok((entity, mut cell)) = cells.get_mut(game.grid[y][x]) {
+			let previous = cell.content.clone();
+			if cell.content == CellContent::Empty {
+				cell.content = CellContent::Rock;
+				graph.set_node_walkability(cell.node_id, false);
+			} else if cell.content != CellContent::Rock {
+				continue;
+			}
+
+			if graph.bfs() {
+				history.rocks.push((event.position, previous));
+				new_path.send(NewPathEvent(graph.path.to_vec()));
+				rock_placed.send(RockPlacedEvent {
+					entity,
+					position: event.position,
+				});
+				placed += 1;
+			} else {
+				cell.content = CellContent::Empty;
+				graph.set_node_walkability(cell.node_id, true);
+			}
+		}
+	}
 }
 
-fn update_cell_sprites(mut query: Query<(&Cell, &mut Sprite)>) {
-	for (cell, mut sprite) in query.iter_mut() {
+/// Removes a rock for `REMOVE_ROCK_COST` or sells a placed tower, this round's candidates stay.
+#[allow(clippy::too_many_arguments)]
+fn clear_cell_event_handler(
+	mut commands: Commands,
+	mut clear_cell: EventReader<ClearCellEvent>,
+	mut game: ResMut<Game>,
+	mut graph: ResMut<Graph>,
+	mut cells: Query<&mut Cell>,
+	towers: Query<&Tower, Without<TemporaryTower>>,
+	mut new_path: EventWriter<NewPathEvent>,
+	mut cell_cleared: EventWriter<CellClearedEvent>,
+) {
+	for event in clear_cell.iter() {
+		let (x, y) = event.position;
+		let mut cell = match game.grid.get(y).and_then(|row| row.get(x)) {
+			Some(entity) => cells.get_mut(*entity).unwrap(),
+			None => continue,
+		};
+
 		match cell.content {
-			CellContent::Limit => sprite.color = Color::WHITE,
-			CellContent::Start => sprite.color = Color::GREEN,
-			CellContent::End => sprite.color = Color::RED,
-			CellContent::Rock => sprite.color = Color::GRAY,
-			CellContent::Tower(_) => sprite.color = Color::GRAY,
-			CellContent::Empty => {}
-			_ => sprite.color = Color::BLUE, // only checkpoints.
+			CellContent::Rock => {
+				if !game.spend_gold(REMOVE_ROCK_COST) {
+					continue;
+				}
+			}
+			CellContent::Tower(entity) => match towers.get(entity) {
+				Ok(tower) => {
+					game.earn_gold(tower.refund());
+					commands.entity(entity).despawn_recursive();
+				}
+				Err(_) => continue,
+			},
+			_ => continue,
+		}
+
+		cell.content = CellContent::Empty;
+		graph.set_node_walkability(cell.node_id, true);
+		if graph.bfs() {
+			new_path.send(NewPathEvent(graph.path.to_vec()));
+		}
+		cell_cleared.send(CellClearedEvent {
+			position: event.position,
+		});
+	}
+}
+
+/// Takes back the last rock of the phase, its gem is kept for the next rock placed.
+#[allow(clippy::too_many_arguments)]
+fn undo_rock_event_handler(
+	mut commands: Commands,
+	mut undo_rock: EventReader<UndoRockEvent>,
+	mut game: ResMut<Game>,
+	mut history: ResMut<BuildHistory>,
+	mut graph: ResMut<Graph>,
+	mut cells: Query<&mut Cell>,
+	towers: Query<&Tower, With<TemporaryTower>>,
+	mut new_path: EventWriter<NewPathEvent>,
+	mut rock_undone: EventWriter<RockUndoneEvent>,
+) {
+	for _ in undo_rock.iter() {
+		let ((x, y), previous) = match history.rocks.pop() {
+			Some(rock) => rock,
+			None => continue,
+		};
+		let mut cell = cells.get_mut(game.grid[y][x]).unwrap();
+		if let CellContent::Tower(entity) = cell.content {
+			let tower = towers.get(entity).unwrap();
+			history
+				.pending_rolls
+				.push((tower.kind.clone(), tower.quality));
+			commands.entity(entity).despawn_recursive();
 		}
+
+		graph.set_node_walkability(cell.node_id, previous == CellContent::Empty);
+		cell.content = previous;
+		game.rocks_count -= 1;
+		if graph.bfs() {
+			new_path.send(NewPathEvent(graph.path.to_vec()));
+		}
+		rock_undone.send(RockUndoneEvent);
 	}
 }
 
+fn clear_build_history(mut history: ResMut<BuildHistory>) {
+	history.rocks.clear();
+}
+
 fn handle_new_rock_placed(
 	mut rock_placed: EventReader<RockPlacedEvent>,
 	mut game: ResMut<Game>,
@@ -125,20 +253,68 @@ fn handle_new_rock_placed(
 	}
 }
 
+/// Asks to place a rock, sent by the player's input or the headless autoplayer.
+pub struct PlaceRockEvent {
+	pub position: (usize, usize),
+}
+
 pub struct RockPlacedEvent {
 	pub entity: Entity,
+	pub position: (usize, usize),
+}
+
+/// Asks to take back the last rock placed in this Build phase.
+pub struct UndoRockEvent;
+
+pub struct RockUndoneEvent;
+
+/// Asks to remove the rock or sell the placed tower at `position`.
+pub struct ClearCellEvent {
+	pub position: (usize, usize),
+}
+
+pub struct CellClearedEvent {
+	pub position: (usize, usize),
 }
 
 pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
 	fn build(&self, app: &mut App) {
+		let initial_state = app
+			.world
+			.get_resource::<SaveGame>()
+			.map_or(AppState::Build, |save| save.state.clone());
+
 		app.insert_resource(Game::default())
+			.insert_resource(BuildHistory::default())
+			.add_state(initial_state)
+			.add_event::<PlaceRockEvent>()
 			.add_event::<RockPlacedEvent>()
+			.add_event::<UndoRockEvent>()
+			.add_event::<RockUndoneEvent>()
+			.add_event::<ClearCellEvent>()
+			.add_event::<CellClearedEvent>()
+			.add_event::<UpdateTowerTooltipEvent>()
+			.add_event::<UpdateGameTooltipEvent>()
 			.add_startup_system(init_game)
-			.add_system(update_cell_sprites)
 			.add_system_set(
-				SystemSet::on_update(AppState::Build).with_system(handle_new_rock_placed),
-			);
+				SystemSet::on_update(AppState::Build)
+					.with_system(place_rock_event_handler.label("place_rock"))
+					// Fixed order, so a replay gets the same result from the actions of a frame.
+					.with_system(
+						clear_cell_event_handler
+							.label("clear_cell")
+							.after("upgrade_chance")
+							.before("undo_rock"),
+					)
+					.with_system(
+						undo_rock_event_handler
+							.label("undo_rock")
+							.before("place_rock"),
+					)
+					.with_system(handle_new_rock_placed.after("spawn_tower")),
+			)
+			.add_system_set(SystemSet::on_enter(AppState::Build).with_system(clear_build_history));
 	}
 }
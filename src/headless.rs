@@ -0,0 +1,118 @@
+use crate::{
+	count_identical, AppState, BuildRecipeEvent, Cell, CellContent, CombineTowersEvent, Game,
+	GameReport, GameRng, KeepTowerEvent, PlaceRockEvent, Recipes, ReplayPlayback, SimulationClock,
+	TemporaryTower, Tower,
+};
+use bevy::app::AppExit;
+use bevy::prelude::*;
+
+fn unbound_clock(mut clock: ResMut<SimulationClock>) {
+	clock.unbounded = true;
+}
+
+/// Tries the empty cells one after the other, the rocks that would block the path are refused.
+fn autoplay_build(
+	mut next_cell: Local<usize>,
+	game: Res<Game>,
+	playback: Option<Res<ReplayPlayback>>,
+	cells: Query<&Cell>,
+	mut place_rock: EventWriter<PlaceRockEvent>,
+) {
+	if playback.is_some() {
+		return;
+	}
+
+	let height = game.grid.len();
+	let width = game.grid.first().map_or(0, |row| row.len());
+	for _ in 0..width * height {
+		let (x, y) = (*next_cell % width, *next_cell / width);
+		*next_cell = (*next_cell + 1) % (width * height);
+		if let Ok(cell) = cells.get(game.grid[y][x]) {
+			if cell.content == CellContent::Empty {
+				place_rock.send(PlaceRockEvent { position: (x, y) });
+				return;
+			}
+		}
+	}
+}
+
+/// Builds the first recipe it finds, then combines the first identical candidates, or keeps the
+/// tower that deals the most damage.
+fn autoplay_select(
+	playback: Option<Res<ReplayPlayback>>,
+	recipes: Res<Recipes>,
+	towers: Query<&Tower, With<TemporaryTower>>,
+	placed: Query<&Tower, Without<TemporaryTower>>,
+	mut keep_tower: EventWriter<KeepTowerEvent>,
+	mut combine_towers: EventWriter<CombineTowersEvent>,
+	mut build_recipe: EventWriter<BuildRecipeEvent>,
+) {
+	if playback.is_some() {
+		return;
+	}
+
+	let candidates: Vec<&Tower> = towers.iter().collect();
+	let placed: Vec<&Tower> = placed.iter().collect();
+	if let Some(tower) = candidates
+		.iter()
+		.find(|tower| recipes.find(tower, &candidates, &placed).is_some())
+	{
+		build_recipe.send(BuildRecipeEvent {
+			position: tower.position,
+		});
+		return;
+	}
+
+	if let Some(tower) = towers.iter().find(|tower| {
+		count_identical(towers.iter(), tower) > 1 && tower.quality.upgraded(1).is_some()
+	}) {
+		combine_towers.send(CombineTowersEvent {
+			position: tower.position,
+		});
+		return;
+	}
+
+	if let Some(tower) = towers
+		.iter()
+		.max_by(|a, b| a.damage.partial_cmp(&b.damage).unwrap())
+	{
+		keep_tower.send(KeepTowerEvent {
+			position: tower.position,
+		});
+	}
+}
+
+fn exit_on_game_over(
+	game: Res<Game>,
+	rng: Res<GameRng>,
+	report: Res<GameReport>,
+	mut app_exit: EventWriter<AppExit>,
+) {
+	for line in report.lines() {
+		println!("{}", line);
+	}
+	println!("Game over at level {} with seed {}.", game.level, rng.seed);
+	app_exit.send(AppExit);
+}
+
+/// Plays without a window, in place of the mouse and the graphics plugins.
+pub struct HeadlessPlugin;
+
+impl Plugin for HeadlessPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_startup_system(unbound_clock)
+			// Before the handlers, so each choice is applied in the frame it is made.
+			.add_system_set(
+				SystemSet::on_update(AppState::Build)
+					.with_system(autoplay_build.before("place_rock")),
+			)
+			.add_system_set(
+				SystemSet::on_update(AppState::Select)
+					.with_system(autoplay_select.before("combine_towers")),
+			)
+			.add_system_set(
+				SystemSet::on_enter(AppState::GameOver)
+					.with_system(exit_on_game_over.after("game_report")),
+			);
+	}
+}
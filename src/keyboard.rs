@@ -0,0 +1,89 @@
+use crate::{
+	AppState, GameReport, ReplayPlayback, ReportSort, SaveGameEvent, TimeScale, UndoRockEvent,
+	UpgradeChanceEvent, QUICKSAVE_PATH,
+};
+use bevy::prelude::*;
+
+const TIME_SCALES: [(KeyCode, u32); 4] = [
+	(KeyCode::Key1, 1),
+	(KeyCode::Key2, 2),
+	(KeyCode::Key3, 4),
+	(KeyCode::Key4, 8),
+];
+
+fn handle_time_scale_keys(keys: Res<Input<KeyCode>>, mut time_scale: ResMut<TimeScale>) {
+	for (key, scale) in TIME_SCALES.iter() {
+		if keys.just_pressed(*key) {
+			time_scale.0 = *scale;
+		}
+	}
+}
+
+fn handle_pause_key(keys: Res<Input<KeyCode>>, mut app_state: ResMut<State<AppState>>) {
+	if keys.just_pressed(KeyCode::Space) {
+		// Fails when the wave just ended and a transition is already queued, the press is then dropped.
+		let _ = match app_state.current() {
+			AppState::Enemies => app_state.push(AppState::Paused),
+			AppState::Paused => app_state.pop(),
+			_ => Ok(()),
+		};
+	}
+}
+
+fn handle_save_key(keys: Res<Input<KeyCode>>, mut save_game: EventWriter<SaveGameEvent>) {
+	if keys.just_pressed(KeyCode::F5) {
+		save_game.send(SaveGameEvent {
+			path: QUICKSAVE_PATH.to_string(),
+		});
+	}
+}
+
+fn handle_upgrade_chance_key(
+	keys: Res<Input<KeyCode>>,
+	playback: Option<Res<ReplayPlayback>>,
+	mut upgrade_chance: EventWriter<UpgradeChanceEvent>,
+) {
+	if keys.just_pressed(KeyCode::U) && playback.is_none() {
+		upgrade_chance.send(UpgradeChanceEvent);
+	}
+}
+
+fn handle_undo_key(
+	keys: Res<Input<KeyCode>>,
+	playback: Option<Res<ReplayPlayback>>,
+	mut undo_rock: EventWriter<UndoRockEvent>,
+) {
+	if keys.just_pressed(KeyCode::Z) && playback.is_none() {
+		undo_rock.send(UndoRockEvent);
+	}
+}
+
+const REPORT_SORTS: [(KeyCode, ReportSort); 4] = [
+	(KeyCode::D, ReportSort::Damage),
+	(KeyCode::K, ReportSort::Kills),
+	(KeyCode::S, ReportSort::Shots),
+	(KeyCode::U, ReportSort::Uptime),
+];
+
+fn handle_report_sort_keys(keys: Res<Input<KeyCode>>, mut report: ResMut<GameReport>) {
+	for (key, sort) in REPORT_SORTS {
+		if keys.just_pressed(key) {
+			report.sort_by(sort);
+		}
+	}
+}
+
+pub struct KeyboardPlugin;
+
+impl Plugin for KeyboardPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_system(handle_time_scale_keys)
+			.add_system(handle_pause_key)
+			.add_system(handle_save_key)
+			.add_system(handle_upgrade_chance_key)
+			.add_system(handle_undo_key)
+			.add_system_set(
+				SystemSet::on_update(AppState::GameOver).with_system(handle_report_sort_keys),
+			);
+	}
+}
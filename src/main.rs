@@ -1,5 +1,8 @@
 // #![allow(unused_mut, dead_code, unused_variables, unused_parens)]
+use bevy::app::PluginGroupBuilder;
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 mod game;
 use game::*;
@@ -17,27 +20,55 @@ mod towers_ai;
 use towers_ai::*;
 mod ui;
 use ui::*;
+mod damage;
+use damage::*;
 mod projectiles;
 use projectiles::*;
 mod modifiers;
 use modifiers::*;
+mod options;
+use options::*;
+mod map;
+use map::*;
+mod rng;
+use rng::*;
+mod simulation;
+use simulation::*;
+mod keyboard;
+use keyboard::*;
+mod replay;
+use replay::*;
+mod chances;
+use chances::*;
+mod recipes;
+use recipes::*;
+mod stats;
+use stats::*;
+mod save;
+use save::*;
+mod sprites;
+use sprites::*;
+mod headless;
+use headless::*;
 
-const GRID_SIZE: f32 = 25.0;
 const TILE_SIZE: f32 = 25.0;
 const TILE_SPACER: f32 = 1.0;
 const WINDOW_WIDTH: f32 = 1280.0;
 const WINDOW_HEIGHT: f32 = 720.0;
 
-fn init_cameras(mut commands: Commands) {
-    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+fn init_cameras(mut commands: Commands, geometry: Res<GridGeometry>) {
+    let mut camera = OrthographicCameraBundle::new_2d();
+    camera.orthographic_projection.scale = geometry.fit_scale(WINDOW_WIDTH, WINDOW_HEIGHT);
+    commands.spawn_bundle(camera);
     commands.spawn_bundle(UiCameraBundle::default());
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
-enum AppState {
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum AppState {
     Build,
     Select,
     Enemies,
+    Paused,
     GameOver,
 }
 
@@ -46,6 +77,7 @@ struct MainPlugin;
 fn game_over(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
+    rng: Res<GameRng>,
     ui_root: Query<Entity, With<UiRoot>>,
     sprites: Query<Entity, With<Sprite>>,
     texture_sprites: Query<Entity, With<TextureAtlasSprite>>,
@@ -73,34 +105,62 @@ fn game_over(
             ..Default::default()
         })
         .with_children(|parent| {
-            parent.spawn_bundle(TextBundle {
-                style: Style {
-                    align_self: AlignSelf::FlexEnd,
-                    ..Default::default()
-                },
-                text: Text {
-                    sections: vec![
-                        TextSection {
-                            value: "Game Over !\n\n".to_string(),
-                            style: TextStyle {
-                                font: font.clone(),
-                                font_size: 50.0,
-                                color: Color::WHITE,
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        align_self: AlignSelf::FlexEnd,
+                        ..Default::default()
+                    },
+                    text: Text {
+                        sections: vec![
+                            TextSection {
+                                value: "Game Over !\n\n".to_string(),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size: 50.0,
+                                    color: Color::WHITE,
+                                },
+                            },
+                            TextSection {
+                                value: format!("Seed: {}\n\n", rng.seed),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size: 30.0,
+                                    color: Color::WHITE,
+                                },
+                            },
+                            TextSection {
+                                value: "".to_string(),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size: 20.0,
+                                    color: Color::GOLD,
+                                },
                             },
-                        },
-                        TextSection {
-                            value: "Press esc to exit.".to_string(),
-                            style: TextStyle {
-                                font,
-                                font_size: 30.0,
-                                color: Color::WHITE,
+                            TextSection {
+                                value: "\n\nSort by D (damage), K (kills), S (shots) \
+                                        or U (uptime).\n\n"
+                                    .to_string(),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size: 20.0,
+                                    color: Color::WHITE,
+                                },
                             },
-                        },
-                    ],
+                            TextSection {
+                                value: "Press esc to exit.".to_string(),
+                                style: TextStyle {
+                                    font,
+                                    font_size: 30.0,
+                                    color: Color::WHITE,
+                                },
+                            },
+                        ],
+                        ..Default::default()
+                    },
                     ..Default::default()
-                },
-                ..Default::default()
-            });
+                })
+                .insert(GameReportText);
         });
 }
 
@@ -113,24 +173,102 @@ impl Plugin for MainPlugin {
             ..Default::default()
         })
         .insert_resource(ClearColor(Color::rgb(0.12, 0.12, 0.12)))
-        .add_state(AppState::Build)
         .add_startup_system(init_cameras)
         .add_system_set(SystemSet::on_enter(AppState::GameOver).with_system(game_over))
         .add_system(bevy::input::system::exit_on_esc_system);
     }
 }
 
+/// Everything needed to play a game, without any window, input or asset.
+pub struct SimulationPlugins;
+
+impl PluginGroup for SimulationPlugins {
+    fn build(&mut self, group: &mut PluginGroupBuilder) {
+        group
+            .add(SimulationPlugin)
+            .add(PathfindingPlugin)
+            .add(GamePlugin)
+            .add(EnemiesPlugin)
+            .add(TowersPlugin)
+            .add(ChancesPlugin)
+            .add(RecipesPlugin)
+            .add(DamagePlugin)
+            .add(TowersAIPlugin)
+            .add(ProjectilesPlugin)
+            .add(StatsPlugin)
+            .add(ReplayPlugin)
+            .add(SavePlugin);
+    }
+}
+
+/// Window, mouse, sprites and UI layered on top of the simulation.
+pub struct GraphicsPlugins;
+
+impl PluginGroup for GraphicsPlugins {
+    fn build(&mut self, group: &mut PluginGroupBuilder) {
+        group
+            .add(MainPlugin)
+            .add(MousePlugin)
+            .add(KeyboardPlugin)
+            .add(SpritesPlugin)
+            .add(UiPlugin);
+    }
+}
+
+/// The map a save or a replay was played on, `--map` can only repeat it.
+fn stored_map(requested: &Option<String>, stored: &Option<String>, source: &str) -> Option<String> {
+    if requested.is_some() && requested != stored {
+        panic!(
+            "The {} was played on {}, not on --map {}.",
+            source,
+            stored.as_deref().unwrap_or("the default map"),
+            requested.as_deref().unwrap_or_default()
+        );
+    }
+    stored.clone()
+}
+
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins)
-        .add_plugin(MainPlugin)
-        .add_plugin(PathfindingPlugin)
-        .add_plugin(MousePlugin)
-        .add_plugin(GamePlugin)
-        .add_plugin(EnemiesPlugin)
-        .add_plugin(TowersPlugin)
-        .add_plugin(TowersAIPlugin)
-        .add_plugin(UiPlugin)
-        .add_plugin(ProjectilesPlugin)
-        .run();
+    let mut options = Options::from_args();
+    let headless = options.headless;
+    let mut app = App::new();
+
+    let mut seed = options.seed;
+    if let Some(path) = options.replay.clone() {
+        let replay = Replay::load(&path)
+            .unwrap_or_else(|error| panic!("Could not load the replay {}: {}", path, error));
+        seed = Some(replay.seed);
+        options.map = stored_map(&options.map, &replay.map, &format!("replay {}", path));
+        app.insert_resource(ReplayPlayback::from(replay));
+    }
+    if let Some(path) = options.load.clone() {
+        let save = SaveGame::load(&path)
+            .unwrap_or_else(|error| panic!("Could not load the save {}: {}", path, error));
+        seed = Some(save.seed);
+        options.map = stored_map(&options.map, &save.map, &format!("save {}", path));
+        app.insert_resource(save);
+    }
+    let map_path = options
+        .map
+        .as_ref()
+        .map_or_else(|| asset_path(DEFAULT_MAP), PathBuf::from);
+    let map = Map::load(&map_path)
+        .unwrap_or_else(|error| panic!("Invalid map {}: {}", map_path.display(), error));
+    if let Some(path) = &options.record {
+        app.insert_resource(ReplayRecorder::new(path.clone()));
+    }
+    app.insert_resource(GameRng::new(seed))
+        .insert_resource(GridGeometry::new(map.width, map.height))
+        .insert_resource(map)
+        .insert_resource(options);
+    if headless {
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(SimulationPlugins)
+            .add_plugin(HeadlessPlugin);
+    } else {
+        app.add_plugins(DefaultPlugins)
+            .add_plugins(SimulationPlugins)
+            .add_plugins(GraphicsPlugins);
+    }
+    app.run();
 }
@@ -0,0 +1,100 @@
+use crate::CellContent;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+pub const DEFAULT_MAP: &str = "maps/default.ron";
+
+fn default_border() -> bool {
+	true
+}
+
+/// A board layout, the slimes walk from `start` through every checkpoint in order to `end`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Map {
+	pub width: usize,
+	pub height: usize,
+	/// Surrounds the board with limit cells.
+	#[serde(default = "default_border")]
+	pub border: bool,
+	#[serde(default)]
+	pub limits: Vec<(usize, usize)>,
+	pub start: (usize, usize),
+	pub end: (usize, usize),
+	pub checkpoints: Vec<(usize, usize)>,
+	#[serde(default)]
+	pub rocks: Vec<(usize, usize)>,
+	/// Waves file of this map in the assets folder, `waves.ron` when missing.
+	#[serde(default)]
+	pub waves: Option<String>,
+}
+
+impl Map {
+	pub fn load(path: &Path) -> Result<Self, String> {
+		let content = fs::read_to_string(path).map_err(|error| error.to_string())?;
+		let map: Self = ron::from_str(&content).map_err(|error| error.to_string())?;
+		map.validate()?;
+		Ok(map)
+	}
+
+	fn validate(&self) -> Result<(), String> {
+		if self.width == 0 || self.height == 0 {
+			return Err(format!(
+				"the map can not be empty, got {}x{}",
+				self.width, self.height
+			));
+		}
+
+		let mut stops = vec![("start", self.start), ("end", self.end)];
+		stops.extend(
+			self.checkpoints
+				.iter()
+				.map(|position| ("checkpoint", *position)),
+		);
+		let mut path_cells = HashSet::new();
+		for (name, position) in stops {
+			if !self.contains(position) {
+				return Err(format!("{} {:?} is outside of the map", name, position));
+			}
+			if self.is_limit(position) || self.rocks.contains(&position) {
+				return Err(format!("{} {:?} is not walkable", name, position));
+			}
+			if !path_cells.insert(position) {
+				return Err(format!("{} {:?} is used twice", name, position));
+			}
+		}
+		for (name, positions) in [("limit", &self.limits), ("rock", &self.rocks)] {
+			if let Some(position) = positions.iter().find(|position| !self.contains(**position)) {
+				return Err(format!("{} {:?} is outside of the map", name, position));
+			}
+		}
+		Ok(())
+	}
+
+	fn contains(&self, (x, y): (usize, usize)) -> bool {
+		x < self.width && y < self.height
+	}
+
+	fn is_limit(&self, (x, y): (usize, usize)) -> bool {
+		(self.border && (x == 0 || y == 0 || x == self.width - 1 || y == self.height - 1))
+			|| self.limits.contains(&(x, y))
+	}
+
+	pub fn content(&self, position: (usize, usize)) -> CellContent {
+		if self.is_limit(position) {
+			CellContent::Limit
+		} else if position == self.start {
+			CellContent::Start
+		} else if position == self.end {
+			CellContent::End
+		} else if let Some(index) = self.checkpoints.iter().position(|c| *c == position) {
+			CellContent::CheckPoint(index)
+		} else if self.rocks.contains(&position) {
+			CellContent::Rock
+		} else {
+			CellContent::Empty
+		}
+	}
+}
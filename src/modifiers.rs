@@ -1,20 +1,75 @@
+use bevy::prelude::Entity;
 use bevy::utils::Duration;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
 
-#[derive(std::cmp::PartialEq, Clone)]
+/// Writes the duration of a debuff as seconds, easier to edit by hand.
+mod seconds {
+	use bevy::utils::Duration;
+	use serde::de::Error;
+	use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+	pub fn serialize<S: Serializer>(
+		debuff: &Option<(f32, Duration)>,
+		serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		debuff
+			.map(|(value, duration)| (value, duration.as_secs_f32()))
+			.serialize(serializer)
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(
+		deserializer: D,
+	) -> Result<Option<(f32, Duration)>, D::Error> {
+		match Option::<(f32, f32)>::deserialize(deserializer)? {
+			None => Ok(None),
+			Some((value, _)) if !value.is_finite() => Err(D::Error::custom(format!(
+				"the value of a debuff must be a number, got {}",
+				value
+			))),
+			Some((value, seconds)) if seconds.is_finite() && seconds > 0.0 => {
+				Ok(Some((value, Duration::from_secs_f32(seconds))))
+			}
+			Some((value, seconds)) => Err(D::Error::custom(format!(
+				"the duration of {} must be a positive number of seconds, got {}",
+				value, seconds
+			))),
+		}
+	}
+}
+
+#[derive(std::cmp::PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Cleave {
 	pub range: f32,
 	pub damage: f32,
 }
 
-#[derive(std::cmp::PartialEq, Clone, Default)]
+/// A hit jumps to the nearest slime not hit yet within `range`, up to `bounces` times. Each hop
+/// keeps `falloff` of the damage of the previous one.
+#[derive(std::cmp::PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Chain {
+	pub range: f32,
+	pub bounces: usize,
+	pub falloff: f32,
+}
+
+#[derive(std::cmp::PartialEq, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct TowerModifier {
 	pub attack_speed: Vec<f32>,
-	pub apply_armor: HashMap<i32, Duration>,
-	pub apply_poison: HashMap<i32, Duration>,
-	pub apply_speed: HashMap<i32, Duration>,
+	#[serde(with = "seconds")]
+	pub apply_armor: Option<(f32, Duration)>,
+	#[serde(with = "seconds")]
+	pub apply_poison: Option<(f32, Duration)>,
+	#[serde(with = "seconds")]
+	pub apply_speed: Option<(f32, Duration)>,
 	pub cleave: Option<Cleave>,
+	pub chain: Option<Chain>,
 	pub target_count: Option<usize>,
+	/// Chance between 0 and 1 for a projectile to deal `crit_multiplier` times its damage.
+	pub crit_chance: f32,
+	pub crit_multiplier: f32,
 }
 
 impl TowerModifier {
@@ -23,14 +78,118 @@ impl TowerModifier {
 	}
 }
 
-#[derive(std::cmp::PartialEq, Clone, Default)]
+#[derive(std::cmp::PartialEq, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct TowerAuras {
 	pub attack_speed: Vec<(f32, f32)>,
 }
 
-#[derive(std::cmp::PartialEq, Clone, Default)]
-pub struct SlimeModifier {
-	pub armor: HashMap<i32, Duration>,
-	pub poison: HashMap<i32, Duration>,
-	pub speed: HashMap<i32, Duration>,
+/// What a status effect changes on a slime.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StatusKind {
+	/// Added to the armor.
+	Armor,
+	/// Magic damage per second.
+	Poison,
+	/// Percent of the speed removed.
+	Slow,
+}
+
+impl StatusKind {
+	/// Armor reductions don't stack, poisons add up to 20 damage per second and only the
+	/// strongest slow applies.
+	pub fn stacking(&self) -> Stacking {
+		match self {
+			Self::Armor => Stacking::Refresh,
+			Self::Poison => Stacking::Additive { cap: 20.0 },
+			Self::Slow => Stacking::Strongest,
+		}
+	}
+}
+
+/// How an effect combines with the other effects of its kind on the same slime.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stacking {
+	/// A single effect, the last one applied replaces it.
+	Refresh,
+	/// One effect per tower, their magnitudes add up to at most `cap`.
+	Additive { cap: f32 },
+	/// One effect per tower, only the largest magnitude applies.
+	Strongest,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatusEffect {
+	pub kind: StatusKind,
+	/// The tower that applied the effect.
+	pub source: Entity,
+	pub magnitude: f32,
+	pub duration: Duration,
+	pub stacking: Stacking,
+}
+
+impl StatusEffect {
+	pub fn new(kind: StatusKind, source: Entity, magnitude: f32, duration: Duration) -> Self {
+		Self {
+			kind,
+			source,
+			magnitude,
+			duration,
+			stacking: kind.stacking(),
+		}
+	}
+}
+
+/// The status effects on a slime, in the order they were applied.
+#[derive(Clone, Default, PartialEq)]
+pub struct StatusEffects(Vec<StatusEffect>);
+
+impl StatusEffects {
+	pub fn apply(&mut self, effect: StatusEffect) {
+		let existing = self.0.iter_mut().find(|other| {
+			other.kind == effect.kind
+				&& (effect.stacking == Stacking::Refresh || other.source == effect.source)
+		});
+		match existing {
+			Some(other) => *other = effect,
+			None => self.0.push(effect),
+		}
+	}
+
+	/// Shortens every effect by `delta`, the ones that run out are removed.
+	pub fn tick(&mut self, delta: Duration) {
+		self.0.retain(|effect| effect.duration > delta);
+		for effect in self.0.iter_mut() {
+			effect.duration -= delta;
+		}
+	}
+
+	pub fn has(&self, kind: StatusKind) -> bool {
+		self.0.iter().any(|effect| effect.kind == kind)
+	}
+
+	pub fn iter(&self, kind: StatusKind) -> impl Iterator<Item = &StatusEffect> {
+		self.0.iter().filter(move |effect| effect.kind == kind)
+	}
+
+	/// The combined magnitude of the effects of `kind`, following their stacking.
+	pub fn magnitude(&self, kind: StatusKind) -> f32 {
+		let mut effects = self.iter(kind).peekable();
+		let stacking = match effects.peek() {
+			Some(effect) => effect.stacking,
+			None => return 0.0,
+		};
+		let magnitudes = effects.map(|effect| effect.magnitude);
+		match stacking {
+			Stacking::Refresh => magnitudes.last().unwrap_or(0.0),
+			Stacking::Additive { cap } => magnitudes.sum::<f32>().clamp(-cap, cap),
+			Stacking::Strongest => magnitudes.fold(0.0, |strongest: f32, magnitude| {
+				if magnitude.abs() > strongest.abs() {
+					magnitude
+				} else {
+					strongest
+				}
+			}),
+		}
+	}
 }
@@ -1,6 +1,7 @@
 use crate::{
-	fit_to_grid, vec2_to_position, AppState, Cell, CellContent, Game, Graph, NewPathEvent,
-	RockPlacedEvent, TemporaryTower, Tower, UpdateTowerTooltipEvent, WINDOW_HEIGHT, WINDOW_WIDTH,
+	AppState, BuildRecipeEvent, Cell, CellContent, ClearCellEvent, CombineTowersEvent, Game,
+	GridGeometry, KeepTowerEvent, PlaceRockEvent, ReplayPlayback, SwapTowersEvent, TemporaryTower,
+	Tower, UpdateTowerTooltipEvent, WINDOW_HEIGHT, WINDOW_WIDTH,
 };
 use bevy::input::{mouse::MouseButtonInput, ElementState};
 use bevy::prelude::*;
@@ -11,6 +12,8 @@ struct MouseState {
 	pressed_read: bool,
 	width: f32,
 	height: f32,
+	/// Scale of the camera, to go from the window to the world.
+	scale: f32,
 }
 
 impl MouseState {
@@ -21,12 +24,17 @@ impl MouseState {
 			pressed_read: true,
 			width,
 			height,
+			scale: 1.0,
 		}
 	}
 
 	fn update(&mut self, position: Vec2) {
-		self.position.x = position.x - (self.width / 2.0);
-		self.position.y = position.y - (self.height / 2.0);
+		self.position.x = (position.x - (self.width / 2.0)) * self.scale;
+		self.position.y = (position.y - (self.height / 2.0)) * self.scale;
+	}
+
+	fn grid_position(&self, geometry: &GridGeometry) -> Option<(usize, usize)> {
+		geometry.fit_to_grid(geometry.vec2_to_position(self.position))
 	}
 }
 
@@ -56,11 +64,12 @@ fn handle_mouse_events(
 
 fn handle_tooltip_hoover(
 	mouse: Res<MouseState>,
+	geometry: Res<GridGeometry>,
 	mut update_ui: EventWriter<UpdateTowerTooltipEvent>,
 	game: Res<Game>,
 	cells: Query<&Cell>,
 ) {
-	if let Some((x, y)) = fit_to_grid(vec2_to_position(mouse.position)) {
+	if let Some((x, y)) = mouse.grid_position(&geometry) {
 		let entity = game.grid[y][x];
 		if let Ok(cell) = cells.get(entity) {
 			if let CellContent::Tower(_) = cell.content {
@@ -74,76 +83,133 @@ fn handle_tooltip_hoover(
 
 fn handle_build_click(
 	mut mouse: ResMut<MouseState>,
-	game: Res<Game>,
-	mut graph: ResMut<Graph>,
-	mut cells: Query<(Entity, &mut Cell)>,
-	mut new_path: EventWriter<NewPathEvent>,
-	mut rock_placed: EventWriter<RockPlacedEvent>,
+	geometry: Res<GridGeometry>,
+	playback: Option<Res<ReplayPlayback>>,
+	mut place_rock: EventWriter<PlaceRockEvent>,
 ) {
-	if mouse.pressed && !mouse.pressed_read {
+	if mouse.pressed && !mouse.pressed_read && playback.is_none() {
 		mouse.pressed_read = true;
-		if let Some((x, y)) = fit_to_grid(vec2_to_position(mouse.position)) {
-			let entity = game.grid[y][x];
-			if let Ok((entity, mut cell)) = cells.get_mut(entity) {
-				if cell.content == CellContent::Empty {
-					cell.content = CellContent::Rock;
-					graph.set_node_walkability(cell.node_id, false);
-				} else if cell.content != CellContent::Rock {
-					return;
-				}
-
-				if graph.bfs() {
-					new_path.send(NewPathEvent(graph.path.to_vec()));
-					rock_placed.send(RockPlacedEvent { entity });
-				} else {
-					cell.content = CellContent::Empty;
-					graph.set_node_walkability(cell.node_id, true);
-				}
-			}
+		if let Some(position) = mouse.grid_position(&geometry) {
+			place_rock.send(PlaceRockEvent { position });
 		}
 	}
 }
 
 fn handle_select_click(
-	mut commands: Commands,
 	mut mouse: ResMut<MouseState>,
-	game: Res<Game>,
-	mut temporary_towers: Query<(Entity, &Tower, &TemporaryTower)>,
-	mut cells: Query<&mut Cell>,
-	mut app_state: ResMut<State<AppState>>,
+	geometry: Res<GridGeometry>,
+	playback: Option<Res<ReplayPlayback>>,
+	mut keep_tower: EventWriter<KeepTowerEvent>,
 ) {
-	if mouse.pressed && !mouse.pressed_read {
+	if mouse.pressed && !mouse.pressed_read && playback.is_none() {
 		mouse.pressed_read = true;
-		let mut found = false;
-		if let Some((x, y)) = fit_to_grid(vec2_to_position(mouse.position)) {
-			for (_, tower, _) in temporary_towers.iter_mut() {
-				if tower.position == (x, y) {
-					found = true;
-				}
-			}
+		if let Some(position) = mouse.grid_position(&geometry) {
+			keep_tower.send(KeepTowerEvent { position });
+		}
+	}
+}
 
-			if found {
-				for (entity, tower, temporary) in temporary_towers.iter_mut() {
-					commands.entity(temporary.ui).despawn_recursive();
-					if tower.position == (x, y) {
-						commands.entity(entity).remove::<TemporaryTower>();
-					} else {
-						let (tx, ty) = tower.position;
-						cells.get_mut(game.grid[ty][tx]).unwrap().content = CellContent::Rock;
-						commands.entity(entity).despawn_recursive();
-					}
+fn handle_clear_key(
+	keys: Res<Input<KeyCode>>,
+	mouse: Res<MouseState>,
+	geometry: Res<GridGeometry>,
+	playback: Option<Res<ReplayPlayback>>,
+	mut clear_cell: EventWriter<ClearCellEvent>,
+) {
+	if keys.just_pressed(KeyCode::X) && playback.is_none() {
+		if let Some(position) = mouse.grid_position(&geometry) {
+			clear_cell.send(ClearCellEvent { position });
+		}
+	}
+}
+
+/// The placed tower picked by a first press of S, cleared when the Build phase ends.
+#[derive(Default)]
+struct SwapPick(Option<Entity>);
+
+/// The first press picks a tower, the second one swaps it with the hovered tower.
+#[allow(clippy::too_many_arguments)]
+fn handle_swap_key(
+	mut picked: ResMut<SwapPick>,
+	keys: Res<Input<KeyCode>>,
+	mouse: Res<MouseState>,
+	geometry: Res<GridGeometry>,
+	game: Res<Game>,
+	playback: Option<Res<ReplayPlayback>>,
+	cells: Query<&Cell>,
+	towers: Query<&Tower, Without<TemporaryTower>>,
+	mut swap_towers: EventWriter<SwapTowersEvent>,
+) {
+	if !keys.just_pressed(KeyCode::S) || playback.is_some() {
+		return;
+	}
+	let position = match mouse.grid_position(&geometry) {
+		Some(position) => position,
+		None => return,
+	};
+
+	// The picked tower may have been sold or undone since, the press picks again.
+	match picked.0.take().and_then(|entity| towers.get(entity).ok()) {
+		Some(from) => swap_towers.send(SwapTowersEvent {
+			from: from.position,
+			to: position,
+		}),
+		None => {
+			if let Ok(Cell {
+				content: CellContent::Tower(entity),
+				..
+			}) = cells.get(game.grid[position.1][position.0])
+			{
+				if towers.get(*entity).is_ok() {
+					picked.0 = Some(*entity);
 				}
-				app_state.set(AppState::Enemies).unwrap();
 			}
 		}
 	}
 }
 
-fn init_mouse(mut windows: ResMut<Windows>) {
+fn reset_swap_pick(mut picked: ResMut<SwapPick>) {
+	picked.0 = None;
+}
+
+fn handle_combine_key(
+	keys: Res<Input<KeyCode>>,
+	mouse: Res<MouseState>,
+	geometry: Res<GridGeometry>,
+	playback: Option<Res<ReplayPlayback>>,
+	mut combine_towers: EventWriter<CombineTowersEvent>,
+) {
+	if keys.just_pressed(KeyCode::C) && playback.is_none() {
+		if let Some(position) = mouse.grid_position(&geometry) {
+			combine_towers.send(CombineTowersEvent { position });
+		}
+	}
+}
+
+fn handle_recipe_key(
+	keys: Res<Input<KeyCode>>,
+	mouse: Res<MouseState>,
+	geometry: Res<GridGeometry>,
+	playback: Option<Res<ReplayPlayback>>,
+	mut build_recipe: EventWriter<BuildRecipeEvent>,
+) {
+	if keys.just_pressed(KeyCode::R) && playback.is_none() {
+		if let Some(position) = mouse.grid_position(&geometry) {
+			build_recipe.send(BuildRecipeEvent { position });
+		}
+	}
+}
+
+fn init_mouse(
+	mut windows: ResMut<Windows>,
+	mut mouse: ResMut<MouseState>,
+	geometry: Res<GridGeometry>,
+) {
 	windows
 		.get_primary_mut()
 		.unwrap()
 		.set_cursor_lock_mode(true);
+	mouse.scale = geometry.fit_scale(mouse.width, mouse.height);
 }
 
 pub struct MousePlugin;
@@ -151,12 +217,22 @@ pub struct MousePlugin;
 impl Plugin for MousePlugin {
 	fn build(&self, app: &mut App) {
 		app.insert_resource(MouseState::new(WINDOW_WIDTH, WINDOW_HEIGHT))
+			.init_resource::<SwapPick>()
 			.add_startup_system(init_mouse)
 			.add_system(handle_mouse_events)
 			.add_system(handle_tooltip_hoover)
-			.add_system_set(SystemSet::on_update(AppState::Build).with_system(handle_build_click))
 			.add_system_set(
-				SystemSet::on_update(AppState::Select).with_system(handle_select_click),
+				SystemSet::on_update(AppState::Build)
+					.with_system(handle_build_click)
+					.with_system(handle_clear_key)
+					.with_system(handle_swap_key),
+			)
+			.add_system_set(SystemSet::on_exit(AppState::Build).with_system(reset_swap_pick))
+			.add_system_set(
+				SystemSet::on_update(AppState::Select)
+					.with_system(handle_select_click)
+					.with_system(handle_combine_key)
+					.with_system(handle_recipe_key),
 			);
 	}
 }
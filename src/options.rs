@@ -0,0 +1,37 @@
+pub struct Options {
+	pub headless: bool,
+	pub seed: Option<u64>,
+	pub record: Option<String>,
+	pub replay: Option<String>,
+	pub load: Option<String>,
+	pub map: Option<String>,
+}
+
+impl Options {
+	pub fn from_args() -> Self {
+		let mut options = Self {
+			headless: false,
+			seed: None,
+			record: None,
+			replay: None,
+			load: None,
+			map: None,
+		};
+		let mut args = std::env::args().skip(1);
+		while let Some(arg) = args.next() {
+			match arg.as_str() {
+				"--headless" => options.headless = true,
+				"--seed" => {
+					let value = args.next().expect("--seed needs a value.");
+					options.seed = Some(value.parse().expect("--seed must be a positive integer."));
+				}
+				"--record" => options.record = Some(args.next().expect("--record needs a path.")),
+				"--replay" => options.replay = Some(args.next().expect("--replay needs a path.")),
+				"--load" => options.load = Some(args.next().expect("--load needs a path.")),
+				"--map" => options.map = Some(args.next().expect("--map needs a path.")),
+				_ => panic!("Unknown argument: {}", arg),
+			}
+		}
+		options
+	}
+}
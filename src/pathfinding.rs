@@ -1,4 +1,3 @@
-use crate::Game;
 use bevy::prelude::*;
 use std::collections::{HashMap, VecDeque};
 use std::hash::{Hash, Hasher};
@@ -53,40 +52,28 @@ pub struct Graph {
    pub path: Vec<NodeId>,
    pub start: NodeId,
    pub end: NodeId,
-   pub checkpoint_1: NodeId,
-   pub checkpoint_2: NodeId,
-   pub checkpoint_3: NodeId,
-   pub checkpoint_4: NodeId,
-   pub checkpoint_5: NodeId,
+   pub checkpoints: Vec<NodeId>,
 }
 
 impl Graph {
-   fn get_neighbors(&self, id: NodeId) -> HashMap<Direction, NodeId> {
-      let mut neighbors = HashMap::new();
-      for direction in vec![
+   /// In a fixed order, so the same board always gives the same path.
+   fn get_neighbors(&self, id: NodeId) -> Vec<NodeId> {
+      [
          Direction::North,
          Direction::South,
          Direction::East,
          Direction::West,
       ]
       .iter()
-      {
-         if let Some(node_id) = self.neighbors.get(&(*direction, id)) {
-            neighbors.insert(*direction, *node_id);
-         }
-      }
-      neighbors
+      .filter_map(|direction| self.neighbors.get(&(*direction, id)).copied())
+      .collect()
    }
 
    fn all_checkpoints(&self) -> Vec<(NodeId, NodeId)> {
-      vec![
-         (self.start, self.checkpoint_1),
-         (self.checkpoint_1, self.checkpoint_2),
-         (self.checkpoint_2, self.checkpoint_3),
-         (self.checkpoint_3, self.checkpoint_4),
-         (self.checkpoint_4, self.checkpoint_5),
-         (self.checkpoint_5, self.end),
-      ]
+      let mut stops = vec![self.start];
+      stops.extend(self.checkpoints.iter());
+      stops.push(self.end);
+      stops.windows(2).map(|pair| (pair[0], pair[1])).collect()
    }
 
    pub fn add(&mut self, walkable: bool, x: usize, y: usize) -> NodeId {
@@ -132,7 +119,7 @@ impl Graph {
       node.walkable = walkable;
    }
 
-   fn is_walkable(&self, node_id: NodeId) -> bool {
+   pub fn is_walkable(&self, node_id: NodeId) -> bool {
       if let Some(node) = self.nodes.get(&node_id) {
          node.walkable
       } else {
@@ -163,7 +150,7 @@ impl Graph {
             break;
          }
 
-         for (_, next_id) in self.get_neighbors(current).iter() {
+         for next_id in self.get_neighbors(current).iter() {
             if self.is_walkable(*next_id) && !came_from.contains_key(next_id) {
                frontier.push_back(*next_id);
                came_from.insert(*next_id, Some(current));
@@ -199,26 +186,6 @@ impl Graph {
    }
 }
 
-fn new_path_event_handle(
-   mut new_path: EventReader<NewPathEvent>,
-   graph: Res<Graph>,
-   game: Res<Game>,
-   mut query: Query<&mut Sprite>,
-) {
-   for event in new_path.iter() {
-      for mut sprite in query.iter_mut() {
-         if sprite.color == Color::PURPLE {
-            sprite.color = Color::BLACK;
-         }
-      }
-      for node_id in event.0.iter() {
-         let (x, y) = graph.get_node_position(*node_id).unwrap();
-         let mut sprite = query.get_mut(game.grid[y][x]).unwrap();
-         sprite.color = Color::PURPLE;
-      }
-   }
-}
-
 pub struct NewPathEvent(pub Vec<NodeId>);
 
 pub struct PathfindingPlugin;
@@ -226,7 +193,6 @@ pub struct PathfindingPlugin;
 impl Plugin for PathfindingPlugin {
    fn build(&self, app: &mut App) {
       app.insert_resource(Graph::default())
-         .add_event::<NewPathEvent>()
-         .add_system(new_path_event_handle);
+         .add_event::<NewPathEvent>();
    }
 }
@@ -1,13 +1,23 @@
-use crate::{Slime, Tower, TowerKind};
+use crate::{
+	flat_distance, AppState, AttackType, DamageEvent, DamageKind, DamageMatrix, GameRng,
+	SimulationClock, SimulationLabel, SimulationStage, Slime, StatusEffect, StatusKind, Tower,
+	TowerKind, TowerModifier,
+};
 
 use bevy::prelude::*;
 use bevy::utils::Duration;
+use rand::Rng;
 
 #[derive(Component)]
 pub struct Projectile {
+	pub kind: TowerKind,
+	tower: Entity,
 	target_position: Vec3,
 	target_enemy: Option<Entity>,
 	damage: f32,
+	attack_type: AttackType,
+	/// The on-hit effects of the tower, applied when the projectile lands.
+	modifiers: TowerModifier,
 	lifetime: Duration,
 	velocity: Vec3,
 }
@@ -15,67 +25,62 @@ pub struct Projectile {
 #[derive(Bundle)]
 pub struct ProjectileBundle {
 	projectile: Projectile,
-	#[bundle]
-	sprite_bundle: SpriteBundle,
+	transform: Transform,
 }
 
 impl Projectile {
 	pub fn new_bundle(
-		texture: Handle<Image>,
 		origin: &Transform,
+		tower_entity: Entity,
 		tower: &Tower,
 		target: Entity,
 		target_position: Vec3,
 	) -> ProjectileBundle {
 		let velocity = (target_position - origin.translation).normalize();
 
-		let color = match tower.kind {
-			TowerKind::Amethyst => Color::FUCHSIA,
-			TowerKind::Aquamarine => Color::AQUAMARINE,
-			TowerKind::Diamond => Color::SILVER,
-			TowerKind::Emerald => Color::DARK_GREEN,
-			TowerKind::Opal => Color::ANTIQUE_WHITE,
-			TowerKind::Ruby => Color::TOMATO,
-			TowerKind::Sapphire => Color::ALICE_BLUE,
-			TowerKind::Topaz => Color::GOLD,
-		};
-
 		ProjectileBundle {
 			projectile: Projectile {
+				kind: tower.kind.clone(),
+				tower: tower_entity,
 				target_enemy: Some(target),
 				lifetime: Duration::from_millis(200),
 				damage: tower.damage,
+				attack_type: tower.attack_type,
+				modifiers: tower.modifiers.clone(),
 				target_position,
 				velocity,
 			},
-			sprite_bundle: SpriteBundle {
-				sprite: Sprite {
-					color,
-					..Default::default()
-				},
-				transform: *origin,
-				texture,
-				..Default::default()
-			},
+			transform: *origin,
 		}
 	}
 }
 
+/// A chain lightning hop, from the slime hit last to the next one.
+pub struct ChainArcEvent {
+	pub from: Vec3,
+	pub to: Vec3,
+}
+
+#[allow(clippy::too_many_arguments)]
 fn update_projectiles(
 	mut commands: Commands,
-	time: Res<Time>,
+	clock: Res<SimulationClock>,
 	mut projectiles: Query<(Entity, &mut Projectile, &mut Transform)>,
-	mut slimes: Query<&mut Slime>,
+	matrix: Res<DamageMatrix>,
+	mut rng: ResMut<GameRng>,
+	mut slimes: Query<(Entity, &Transform, &mut Slime), Without<Projectile>>,
+	mut damage_dealt: EventWriter<DamageEvent>,
+	mut chain_arcs: EventWriter<ChainArcEvent>,
 ) {
 	for (entity, mut projectile, mut transform) in projectiles.iter_mut() {
 		if projectile.target_enemy.is_none() {
 			commands.entity(entity).despawn_recursive();
 			return;
 		}
-		if projectile.lifetime <= time.delta() {
+		if projectile.lifetime <= clock.step {
 			projectile.lifetime = Duration::from_secs(0);
 		} else {
-			projectile.lifetime -= time.delta();
+			projectile.lifetime -= clock.step;
 		}
 
 		if projectile
@@ -84,13 +89,118 @@ fn update_projectiles(
 			|| projectile.lifetime <= Duration::ZERO
 		{
 			commands.entity(entity).despawn_recursive();
-			if let Ok(mut slime) = slimes.get_mut(projectile.target_enemy.unwrap()) {
-				slime.take_physical_damage(projectile.damage);
+			let target = projectile.target_enemy.unwrap();
+			let modifiers = &projectile.modifiers;
+			if let Ok((_, &impact, mut slime)) = slimes.get_mut(target) {
+				// The debuffs land first, the armor reduction counts for this hit.
+				for (kind, debuff) in [
+					(StatusKind::Armor, modifiers.apply_armor),
+					(StatusKind::Poison, modifiers.apply_poison),
+					(StatusKind::Slow, modifiers.apply_speed),
+				] {
+					if let Some((value, duration)) = debuff {
+						slime.effects.apply(StatusEffect::new(
+							kind,
+							projectile.tower,
+							value,
+							duration,
+						));
+					}
+				}
+
+				let mut damage = projectile.damage;
+				// Only rolled by the towers that can crit, the others leave the random stream alone.
+				let critical =
+					modifiers.crit_chance > 0.0 && rng.gen_bool(modifiers.crit_chance as f64);
+				if critical {
+					damage *= modifiers.crit_multiplier;
+				}
+				let multiplier = matrix.multiplier(projectile.attack_type, slime.armor_type);
+				let amount = slime.take_physical_damage(damage * multiplier, projectile.tower);
+				if amount > 0 {
+					damage_dealt.send(DamageEvent {
+						tower: projectile.tower,
+						kind: DamageKind::Physical,
+						amount,
+						critical,
+					});
+				}
+
+				// Around the impact, the target included.
+				if let Some(cleave) = &modifiers.cleave {
+					for (_, slime_transform, mut slime) in slimes.iter_mut() {
+						if flat_distance(*slime_transform, impact) <= cleave.range {
+							let amount = slime.take_pure_damage(
+								(projectile.damage * cleave.damage) as usize,
+								projectile.tower,
+							);
+							if amount > 0 {
+								damage_dealt.send(DamageEvent {
+									tower: projectile.tower,
+									kind: DamageKind::Pure,
+									amount,
+									critical: false,
+								});
+							}
+						}
+					}
+				}
+
+				if let Some(chain) = &modifiers.chain {
+					// Like the crit flag, the crit damage stays on the first hit.
+					let mut damage = projectile.damage;
+					let mut hit = vec![target];
+					let mut from = impact;
+					for _ in 0..chain.bounces {
+						let next = slimes
+							.iter()
+							.filter(|(entity, _, slime)| !hit.contains(entity) && slime.life > 0)
+							.map(|(entity, transform, _)| {
+								(entity, *transform, flat_distance(from, *transform))
+							})
+							.filter(|(_, _, distance)| *distance <= chain.range)
+							.min_by(|a, b| a.2.partial_cmp(&b.2).unwrap());
+						let (next, to) = match next {
+							Some((next, to, _)) => (next, to),
+							None => break,
+						};
+
+						damage *= chain.falloff;
+						let (_, _, mut slime) = slimes.get_mut(next).unwrap();
+						let multiplier =
+							matrix.multiplier(projectile.attack_type, slime.armor_type);
+						let amount =
+							slime.take_physical_damage(damage * multiplier, projectile.tower);
+						if amount > 0 {
+							damage_dealt.send(DamageEvent {
+								tower: projectile.tower,
+								kind: DamageKind::Physical,
+								amount,
+								// The crit is counted once, on the first hit.
+								critical: false,
+							});
+						}
+						chain_arcs.send(ChainArcEvent {
+							from: from.translation,
+							to: to.translation,
+						});
+						hit.push(next);
+						from = to;
+					}
+				}
 			}
 		}
 
-		transform.translation.x += projectile.velocity.x * time.delta_seconds() * 1000.0;
-		transform.translation.y += projectile.velocity.y * time.delta_seconds() * 1000.0;
+		transform.translation.x += projectile.velocity.x * clock.delta_seconds() * 1000.0;
+		transform.translation.y += projectile.velocity.y * clock.delta_seconds() * 1000.0;
+	}
+}
+
+/// The projectiles still flying when a wave ends have no target left. They are removed, so the
+/// next wave does not depend on how many frames the Build phase took.
+fn clear_projectiles(mut commands: Commands, projectiles: Query<Entity, With<Projectile>>) {
+	for entity in projectiles.iter() {
+		commands.entity(entity).despawn_recursive();
 	}
 }
 
@@ -98,6 +208,13 @@ pub struct ProjectilesPlugin;
 
 impl Plugin for ProjectilesPlugin {
 	fn build(&self, app: &mut App) {
-		app.add_system(update_projectiles);
+		app.add_event::<ChainArcEvent>()
+			.add_system_to_stage(
+				SimulationStage,
+				update_projectiles
+					.label(SimulationLabel::Projectiles)
+					.after(SimulationLabel::Shoot),
+			)
+			.add_system_set(SystemSet::on_enter(AppState::Build).with_system(clear_projectiles));
 	}
 }
@@ -0,0 +1,193 @@
+use crate::{
+	asset_path, AppState, Cell, CellContent, Game, GridGeometry, TemporaryTower, Tower,
+	TowerDefinitions, TowerKind, TowerQuality, TowersCombinedEvent,
+};
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+/// A special tower built from a set of gems, each ingredient is a kind and an exact quality.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Recipe {
+	pub kind: TowerKind,
+	pub quality: TowerQuality,
+	pub ingredients: Vec<(TowerKind, TowerQuality)>,
+}
+
+impl Recipe {
+	fn validate(&self) -> Result<(), String> {
+		if self.kind.is_gem() {
+			return Err(format!("{:?} is a gem, not a special tower", self.kind));
+		}
+		if self.ingredients.len() < 2 {
+			return Err("there must be at least two ingredients".to_string());
+		}
+		if let Some((kind, _)) = self.ingredients.iter().find(|(kind, _)| !kind.is_gem()) {
+			return Err(format!("{:?} is not a gem", kind));
+		}
+		Ok(())
+	}
+
+	/// The towers used to build the recipe around `chosen`, the candidates are used before the
+	/// placed towers. `None` when one of the ingredients is missing.
+	fn find_ingredients<'a>(
+		&self,
+		chosen: &'a Tower,
+		candidates: &[&'a Tower],
+		placed: &[&'a Tower],
+	) -> Option<Vec<&'a Tower>> {
+		let mut ingredients = self.ingredients.clone();
+		let index = ingredients
+			.iter()
+			.position(|(kind, quality)| *kind == chosen.kind && *quality == chosen.quality)?;
+		ingredients.remove(index);
+
+		let mut used = vec![chosen];
+		for (kind, quality) in ingredients.iter() {
+			let tower = candidates.iter().chain(placed.iter()).find(|tower| {
+				tower.kind == *kind
+					&& tower.quality == *quality
+					&& !used.iter().any(|other| other.position == tower.position)
+			})?;
+			used.push(tower);
+		}
+		Some(used)
+	}
+}
+
+/// The special towers, read from `assets/recipes.ron`. The first recipe that matches is built.
+pub struct Recipes(Vec<Recipe>);
+
+impl Recipes {
+	pub fn load(path: &Path) -> Result<Self, String> {
+		let content = fs::read_to_string(path).map_err(|error| error.to_string())?;
+		let recipes: Vec<Recipe> = ron::from_str(&content).map_err(|error| error.to_string())?;
+		for recipe in recipes.iter() {
+			recipe
+				.validate()
+				.map_err(|error| format!("{:?}: {}", recipe.kind, error))?;
+		}
+		Ok(Self(recipes))
+	}
+
+	/// The first recipe `chosen` is an ingredient of, with all its ingredients.
+	pub fn find<'a>(
+		&'a self,
+		chosen: &'a Tower,
+		candidates: &[&'a Tower],
+		placed: &[&'a Tower],
+	) -> Option<(&'a Recipe, Vec<&'a Tower>)> {
+		self.0.iter().find_map(|recipe| {
+			recipe
+				.find_ingredients(chosen, candidates, placed)
+				.map(|ingredients| (recipe, ingredients))
+		})
+	}
+}
+
+/// Asks to build a recipe with the temporary tower at `position`, the tower is replaced by the
+/// special tower and the other ingredients become rocks.
+pub struct BuildRecipeEvent {
+	pub position: (usize, usize),
+}
+
+pub struct RecipeBuiltEvent {
+	pub position: (usize, usize),
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_recipe_event_handler(
+	mut commands: Commands,
+	mut build_recipe: EventReader<BuildRecipeEvent>,
+	game: Res<Game>,
+	recipes: Res<Recipes>,
+	definitions: Res<TowerDefinitions>,
+	geometry: Res<GridGeometry>,
+	candidates: Query<(Entity, &Tower), With<TemporaryTower>>,
+	placed: Query<(Entity, &Tower), Without<TemporaryTower>>,
+	mut cells: Query<&mut Cell>,
+	mut app_state: ResMut<State<AppState>>,
+	mut towers_combined: EventReader<TowersCombinedEvent>,
+	mut recipe_built: EventWriter<RecipeBuiltEvent>,
+) {
+	// The round already ended with a combination.
+	if towers_combined.iter().count() > 0 {
+		build_recipe.iter().for_each(drop);
+		return;
+	}
+
+	for event in build_recipe.iter() {
+		let (entity, chosen) = match candidates
+			.iter()
+			.find(|(_, tower)| tower.position == event.position)
+		{
+			Some(candidate) => candidate,
+			None => continue,
+		};
+		let candidate_towers: Vec<&Tower> = candidates.iter().map(|(_, tower)| tower).collect();
+		let placed_towers: Vec<&Tower> = placed.iter().map(|(_, tower)| tower).collect();
+		let (recipe, ingredients) = match recipes.find(chosen, &candidate_towers, &placed_towers) {
+			Some(found) => found,
+			None => continue,
+		};
+
+		let (x, y) = chosen.position;
+		let tower = Tower::new(
+			recipe.kind.clone(),
+			recipe.quality,
+			chosen.cell,
+			chosen.position,
+			&definitions,
+		);
+		let tower_id = commands
+			.spawn()
+			.insert(geometry.position_to_transform(x as f32, y as f32))
+			.insert(tower)
+			.id();
+		commands.entity(entity).despawn_recursive();
+		cells.get_mut(game.grid[y][x]).unwrap().content = CellContent::Tower(tower_id);
+
+		// The placed ingredients and every other candidate become rocks.
+		let used_placed = placed.iter().filter(|(_, tower)| {
+			ingredients
+				.iter()
+				.any(|ingredient| ingredient.position == tower.position)
+		});
+		for (other, tower) in candidates.iter().chain(used_placed) {
+			if tower.position != event.position {
+				let (tx, ty) = tower.position;
+				cells.get_mut(game.grid[ty][tx]).unwrap().content = CellContent::Rock;
+				commands.entity(other).despawn_recursive();
+			}
+		}
+
+		recipe_built.send(RecipeBuiltEvent {
+			position: event.position,
+		});
+		app_state.set(AppState::Enemies).unwrap();
+		return;
+	}
+}
+
+pub struct RecipesPlugin;
+
+impl Plugin for RecipesPlugin {
+	fn build(&self, app: &mut App) {
+		let path = asset_path("recipes.ron");
+		let recipes = Recipes::load(&path)
+			.unwrap_or_else(|error| panic!("Invalid recipes {}: {}", path.display(), error));
+
+		app.insert_resource(recipes)
+			.add_event::<BuildRecipeEvent>()
+			.add_event::<RecipeBuiltEvent>()
+			.add_system_set(
+				SystemSet::on_update(AppState::Select).with_system(
+					build_recipe_event_handler
+						.label("build_recipe")
+						.after("combine_towers"),
+				),
+			);
+	}
+}
@@ -0,0 +1,335 @@
+use crate::{
+	AppState, BuildRecipeEvent, CellClearedEvent, ChanceUpgradedEvent, ClearCellEvent,
+	CombineTowersEvent, Game, GameRng, KeepTowerEvent, Options, PlaceRockEvent, RecipeBuiltEvent,
+	RockPlacedEvent, RockUndoneEvent, SwapTowersEvent, TowerKeptEvent, TowersCombinedEvent,
+	TowersSwappedEvent, UndoRockEvent, UpgradeChanceEvent,
+};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+
+/// Bumped when the actions or the random draws change, an older replay would play another game.
+const REPLAY_VERSION: u32 = 2;
+
+/// A player action, tagged with the level it was taken at.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ReplayAction {
+	PlaceRock {
+		level: u8,
+		position: (usize, usize),
+	},
+	KeepTower {
+		level: u8,
+		position: (usize, usize),
+	},
+	UpgradeChance {
+		level: u8,
+	},
+	CombineTowers {
+		level: u8,
+		position: (usize, usize),
+	},
+	BuildRecipe {
+		level: u8,
+		position: (usize, usize),
+	},
+	ClearCell {
+		level: u8,
+		position: (usize, usize),
+	},
+	UndoRock {
+		level: u8,
+	},
+	SwapTowers {
+		level: u8,
+		from: (usize, usize),
+		to: (usize, usize),
+	},
+}
+
+impl ReplayAction {
+	fn level(&self) -> u8 {
+		match self {
+			Self::PlaceRock { level, .. } => *level,
+			Self::KeepTower { level, .. } => *level,
+			Self::UpgradeChance { level } => *level,
+			Self::CombineTowers { level, .. } => *level,
+			Self::BuildRecipe { level, .. } => *level,
+			Self::ClearCell { level, .. } => *level,
+			Self::UndoRock { level } => *level,
+			Self::SwapTowers { level, .. } => *level,
+		}
+	}
+
+	/// The phase the action is taken in.
+	fn phase(&self) -> AppState {
+		match self {
+			Self::PlaceRock { .. }
+			| Self::UpgradeChance { .. }
+			| Self::ClearCell { .. }
+			| Self::SwapTowers { .. }
+			| Self::UndoRock { .. } => AppState::Build,
+			Self::KeepTower { .. } | Self::CombineTowers { .. } | Self::BuildRecipe { .. } => {
+				AppState::Select
+			}
+		}
+	}
+}
+
+/// The seed and the player actions are enough to play a whole run again.
+#[derive(Serialize, Deserialize)]
+pub struct Replay {
+	pub version: u32,
+	pub seed: u64,
+	/// The `--map` of the run, the default map when missing.
+	#[serde(default)]
+	pub map: Option<String>,
+	pub actions: Vec<ReplayAction>,
+}
+
+impl Replay {
+	pub fn load(path: &str) -> Result<Self, String> {
+		let content = fs::read_to_string(path).map_err(|error| error.to_string())?;
+		let replay: Self = ron::from_str(&content).map_err(|error| error.to_string())?;
+		if replay.version != REPLAY_VERSION {
+			return Err(format!(
+				"version {} is not supported, expected {}",
+				replay.version, REPLAY_VERSION
+			));
+		}
+		Ok(replay)
+	}
+
+	fn save(&self, path: &str) -> Result<(), String> {
+		let content = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())
+			.map_err(|error| error.to_string())?;
+		fs::write(path, content).map_err(|error| error.to_string())
+	}
+}
+
+/// Collects the actions of the current run, written to `path` after each round.
+pub struct ReplayRecorder {
+	path: String,
+	actions: Vec<ReplayAction>,
+}
+
+impl ReplayRecorder {
+	pub fn new(path: String) -> Self {
+		Self {
+			path,
+			actions: vec![],
+		}
+	}
+}
+
+/// The actions left to feed back into the game, player input is ignored while it exists.
+pub struct ReplayPlayback {
+	actions: VecDeque<ReplayAction>,
+}
+
+impl From<Replay> for ReplayPlayback {
+	fn from(replay: Replay) -> Self {
+		Self {
+			actions: replay.actions.into(),
+		}
+	}
+}
+
+/// The result events the actions are recorded from.
+#[derive(SystemParam)]
+struct ActionEvents<'w, 's> {
+	chance_upgraded: EventReader<'w, 's, ChanceUpgradedEvent>,
+	cell_cleared: EventReader<'w, 's, CellClearedEvent>,
+	towers_swapped: EventReader<'w, 's, TowersSwappedEvent>,
+	rock_undone: EventReader<'w, 's, RockUndoneEvent>,
+	rock_placed: EventReader<'w, 's, RockPlacedEvent>,
+	tower_kept: EventReader<'w, 's, TowerKeptEvent>,
+	towers_combined: EventReader<'w, 's, TowersCombinedEvent>,
+	recipe_built: EventReader<'w, 's, RecipeBuiltEvent>,
+}
+
+impl<'w, 's> ActionEvents<'w, 's> {
+	/// Called after every handler, so the events are those of one frame. They are read in the
+	/// order the handlers run: upgrades, cleared cells, swaps, undos, rocks, then combinations,
+	/// recipes and kept towers.
+	fn read(&mut self, level: u8) -> Vec<ReplayAction> {
+		let mut actions = vec![];
+		for _ in self.chance_upgraded.iter() {
+			actions.push(ReplayAction::UpgradeChance { level });
+		}
+		for event in self.cell_cleared.iter() {
+			actions.push(ReplayAction::ClearCell {
+				level,
+				position: event.position,
+			});
+		}
+		for event in self.towers_swapped.iter() {
+			actions.push(ReplayAction::SwapTowers {
+				level,
+				from: event.from,
+				to: event.to,
+			});
+		}
+		for _ in self.rock_undone.iter() {
+			actions.push(ReplayAction::UndoRock { level });
+		}
+		for event in self.rock_placed.iter() {
+			actions.push(ReplayAction::PlaceRock {
+				level,
+				position: event.position,
+			});
+		}
+		for event in self.towers_combined.iter() {
+			actions.push(ReplayAction::CombineTowers {
+				level,
+				position: event.position,
+			});
+		}
+		for event in self.recipe_built.iter() {
+			actions.push(ReplayAction::BuildRecipe {
+				level,
+				position: event.position,
+			});
+		}
+		for event in self.tower_kept.iter() {
+			actions.push(ReplayAction::KeepTower {
+				level,
+				position: event.position,
+			});
+		}
+		actions
+	}
+}
+
+fn record_actions(
+	mut events: ActionEvents,
+	game: Res<Game>,
+	recorder: Option<ResMut<ReplayRecorder>>,
+) {
+	if let Some(mut recorder) = recorder {
+		let actions = events.read(game.level);
+		recorder.actions.extend(actions);
+	}
+}
+
+fn save_replay(recorder: Option<Res<ReplayRecorder>>, rng: Res<GameRng>, options: Res<Options>) {
+	if let Some(recorder) = recorder {
+		let replay = Replay {
+			version: REPLAY_VERSION,
+			seed: rng.seed,
+			map: options.map.clone(),
+			actions: recorder.actions.clone(),
+		};
+		if let Err(error) = replay.save(&recorder.path) {
+			println!("Could not save the replay {}: {}", recorder.path, error);
+		}
+	}
+}
+
+/// The request events the actions are sent back as.
+#[derive(SystemParam)]
+struct ActionWriters<'w, 's> {
+	place_rock: EventWriter<'w, 's, PlaceRockEvent>,
+	keep_tower: EventWriter<'w, 's, KeepTowerEvent>,
+	upgrade_chance: EventWriter<'w, 's, UpgradeChanceEvent>,
+	combine_towers: EventWriter<'w, 's, CombineTowersEvent>,
+	build_recipe: EventWriter<'w, 's, BuildRecipeEvent>,
+	clear_cell: EventWriter<'w, 's, ClearCellEvent>,
+	undo_rock: EventWriter<'w, 's, UndoRockEvent>,
+	swap_towers: EventWriter<'w, 's, SwapTowersEvent>,
+}
+
+impl<'w, 's> ActionWriters<'w, 's> {
+	fn send(&mut self, action: &ReplayAction) {
+		match action {
+			ReplayAction::PlaceRock { position, .. } => self.place_rock.send(PlaceRockEvent {
+				position: *position,
+			}),
+			ReplayAction::KeepTower { position, .. } => self.keep_tower.send(KeepTowerEvent {
+				position: *position,
+			}),
+			ReplayAction::UpgradeChance { .. } => self.upgrade_chance.send(UpgradeChanceEvent),
+			ReplayAction::CombineTowers { position, .. } => {
+				self.combine_towers.send(CombineTowersEvent {
+					position: *position,
+				})
+			}
+			ReplayAction::BuildRecipe { position, .. } => {
+				self.build_recipe.send(BuildRecipeEvent {
+					position: *position,
+				})
+			}
+			ReplayAction::ClearCell { position, .. } => self.clear_cell.send(ClearCellEvent {
+				position: *position,
+			}),
+			ReplayAction::UndoRock { .. } => self.undo_rock.send(UndoRockEvent),
+			ReplayAction::SwapTowers { from, to, .. } => self.swap_towers.send(SwapTowersEvent {
+				from: *from,
+				to: *to,
+			}),
+		}
+	}
+}
+
+/// Sends the next action once the game reaches its level and phase, one per frame.
+fn feed_replay_actions(
+	mut commands: Commands,
+	playback: Option<ResMut<ReplayPlayback>>,
+	game: Res<Game>,
+	app_state: Res<State<AppState>>,
+	mut writers: ActionWriters,
+) {
+	if let Some(mut playback) = playback {
+		while let Some(action) = playback.actions.front() {
+			if action.level() < game.level {
+				println!("Skipping {:?}, the replay is out of sync.", action);
+				playback.actions.pop_front();
+			} else {
+				break;
+			}
+		}
+
+		match playback.actions.front() {
+			None => {
+				println!("Replay finished.");
+				commands.remove_resource::<ReplayPlayback>();
+			}
+			Some(action) if action.level() > game.level => {}
+			Some(action) if action.phase() == *app_state.current() => {
+				writers.send(action);
+				playback.actions.pop_front();
+			}
+			_ => {}
+		}
+	}
+}
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_system(
+			record_actions
+				.label("record_actions")
+				// After every handler, so each batch of events comes from a single frame.
+				.after("upgrade_chance")
+				.after("clear_cell")
+				.after("swap_towers")
+				.after("undo_rock")
+				.after("place_rock")
+				.after("combine_towers")
+				.after("build_recipe")
+				.after("keep_tower"),
+		)
+		.add_system(feed_replay_actions)
+		.add_system_set(
+			SystemSet::on_enter(AppState::Enemies).with_system(save_replay.after("record_actions")),
+		)
+		.add_system_set(
+			SystemSet::on_enter(AppState::GameOver)
+				.with_system(save_replay.after("record_actions")),
+		);
+	}
+}
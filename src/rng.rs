@@ -0,0 +1,46 @@
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// The only source of randomness of the simulation, so a seed always replays the same game.
+pub struct GameRng {
+	pub seed: u64,
+	rng: ChaCha8Rng,
+}
+
+impl GameRng {
+	pub fn new(seed: Option<u64>) -> Self {
+		let seed = seed.unwrap_or_else(rand::random);
+		println!("Seed: {}", seed);
+		Self {
+			seed,
+			rng: ChaCha8Rng::seed_from_u64(seed),
+		}
+	}
+
+	/// Position in the random stream, saved along the seed to resume a game.
+	pub fn word_pos(&self) -> u128 {
+		self.rng.get_word_pos()
+	}
+
+	pub fn set_word_pos(&mut self, word_pos: u128) {
+		self.rng.set_word_pos(word_pos)
+	}
+}
+
+impl RngCore for GameRng {
+	fn next_u32(&mut self) -> u32 {
+		self.rng.next_u32()
+	}
+
+	fn next_u64(&mut self) -> u64 {
+		self.rng.next_u64()
+	}
+
+	fn fill_bytes(&mut self, dest: &mut [u8]) {
+		self.rng.fill_bytes(dest)
+	}
+
+	fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+		self.rng.try_fill_bytes(dest)
+	}
+}
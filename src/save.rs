@@ -0,0 +1,298 @@
+use crate::{
+	AppState, BuildHistory, Cell, CellContent, Game, GameRng, Graph, GridGeometry, NewPathEvent,
+	Options, ReplayPlayback, TemporaryTower, Tower, TowerAuras, TowerDefinitions, TowerKind,
+	TowerModifier, TowerQuality, TowerStats,
+};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+const SAVE_VERSION: u32 = 11;
+const AUTOSAVE_PATH: &str = "autosave.ron";
+pub const QUICKSAVE_PATH: &str = "save.ron";
+
+/// `CellContent` without the tower entity, which is spawned again on load.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+enum SavedContent {
+	Empty,
+	Limit,
+	Start,
+	End,
+	Rock,
+	Tower,
+	CheckPoint(usize),
+}
+
+impl From<&CellContent> for SavedContent {
+	fn from(content: &CellContent) -> Self {
+		match content {
+			CellContent::Empty => Self::Empty,
+			CellContent::Limit => Self::Limit,
+			CellContent::Start => Self::Start,
+			CellContent::End => Self::End,
+			CellContent::Rock => Self::Rock,
+			CellContent::Tower(_) => Self::Tower,
+			CellContent::CheckPoint(index) => Self::CheckPoint(*index),
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedCell {
+	position: (usize, usize),
+	content: SavedContent,
+	walkable: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedTower {
+	kind: TowerKind,
+	quality: TowerQuality,
+	position: (usize, usize),
+	temporary: bool,
+	modifiers: TowerModifier,
+	auras: TowerAuras,
+	level: u8,
+	experience: u32,
+	stats: TowerStats,
+}
+
+/// A game stopped in the Build or Select phase, the waves are never saved.
+#[derive(Serialize, Deserialize)]
+pub struct SaveGame {
+	pub version: u32,
+	pub seed: u64,
+	pub word_pos: u128,
+	pub state: AppState,
+	/// The `--map` the game was started with, the default map when missing.
+	pub map: Option<String>,
+	lives: u8,
+	level: u8,
+	rocks_count: u8,
+	chance_tier: u8,
+	gold: u32,
+	/// Gems of the undone rocks, so loading never rerolls them.
+	pending_rolls: Vec<(TowerKind, TowerQuality)>,
+	cells: Vec<SavedCell>,
+	towers: Vec<SavedTower>,
+}
+
+impl SaveGame {
+	pub fn load(path: &str) -> Result<Self, String> {
+		let content = fs::read_to_string(path).map_err(|error| error.to_string())?;
+		let save: Self = ron::from_str(&content).map_err(|error| error.to_string())?;
+		if save.version != SAVE_VERSION {
+			return Err(format!(
+				"version {} is not supported, expected {}",
+				save.version, SAVE_VERSION
+			));
+		}
+		Ok(save)
+	}
+
+	/// Every cell and tower must be on the board the game is loaded on.
+	fn check_geometry(&self, geometry: &GridGeometry) -> Result<(), String> {
+		let positions = self
+			.cells
+			.iter()
+			.map(|cell| cell.position)
+			.chain(self.towers.iter().map(|tower| tower.position));
+		for (x, y) in positions {
+			if x >= geometry.width || y >= geometry.height {
+				return Err(format!(
+					"({}, {}) is outside of the {}x{} map",
+					x, y, geometry.width, geometry.height
+				));
+			}
+		}
+		Ok(())
+	}
+
+	fn save(&self, path: &str) -> Result<(), String> {
+		let content = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())
+			.map_err(|error| error.to_string())?;
+		fs::write(path, content).map_err(|error| error.to_string())
+	}
+}
+
+/// Asks to write the current game to `path`, ignored outside of the Build and Select phases.
+pub struct SaveGameEvent {
+	pub path: String,
+}
+
+/// Everything a save is made of.
+#[derive(SystemParam)]
+struct SaveSources<'w, 's> {
+	game: Res<'w, Game>,
+	history: Res<'w, BuildHistory>,
+	graph: Res<'w, Graph>,
+	rng: Res<'w, GameRng>,
+	options: Res<'w, Options>,
+	cells: Query<'w, 's, &'static Cell>,
+	towers: Query<'w, 's, (&'static Tower, Option<&'static TemporaryTower>)>,
+}
+
+impl<'w, 's> SaveSources<'w, 's> {
+	fn write(&self, path: &str, state: &AppState) {
+		let save = SaveGame {
+			version: SAVE_VERSION,
+			seed: self.rng.seed,
+			word_pos: self.rng.word_pos(),
+			state: state.clone(),
+			map: self.options.map.clone(),
+			lives: self.game.lives,
+			level: self.game.level,
+			rocks_count: self.game.rocks_count,
+			chance_tier: self.game.chance_tier,
+			gold: self.game.gold,
+			pending_rolls: self.history.pending_rolls.clone(),
+			cells: self
+				.cells
+				.iter()
+				.map(|cell| SavedCell {
+					position: cell.position,
+					content: SavedContent::from(&cell.content),
+					walkable: self.graph.is_walkable(cell.node_id),
+				})
+				.collect(),
+			towers: self
+				.towers
+				.iter()
+				.map(|(tower, temporary)| SavedTower {
+					kind: tower.kind.clone(),
+					quality: tower.quality,
+					position: tower.position,
+					temporary: temporary.is_some(),
+					modifiers: tower.modifiers.clone(),
+					auras: tower.auras.clone(),
+					level: tower.level,
+					experience: tower.experience,
+					stats: tower.stats.clone(),
+				})
+				.collect(),
+		};
+		match save.save(path) {
+			Ok(()) => println!("Game saved to {}.", path),
+			Err(error) => println!("Could not save the game {}: {}", path, error),
+		}
+	}
+}
+
+/// Only for the games played by hand, headless runs and replays would leave a file in the
+/// working directory of each run.
+fn autosave(sources: SaveSources, playback: Option<Res<ReplayPlayback>>) {
+	if sources.options.headless || playback.is_some() {
+		return;
+	}
+	sources.write(AUTOSAVE_PATH, &AppState::Build);
+}
+
+fn save_game_event_handler(
+	mut save_game: EventReader<SaveGameEvent>,
+	app_state: Res<State<AppState>>,
+	sources: SaveSources,
+) {
+	for event in save_game.iter() {
+		let state = app_state.current();
+		if *state != AppState::Build && *state != AppState::Select {
+			println!("Can only save during the Build and Select phases.");
+			continue;
+		}
+		sources.write(&event.path, state);
+	}
+}
+
+/// Applies the save given with `--load` over the fresh grid, the state was set by `GamePlugin`.
+#[allow(clippy::too_many_arguments)]
+fn load_game(
+	mut commands: Commands,
+	save: Option<Res<SaveGame>>,
+	mut game: ResMut<Game>,
+	mut history: ResMut<BuildHistory>,
+	mut graph: ResMut<Graph>,
+	mut rng: ResMut<GameRng>,
+	geometry: Res<GridGeometry>,
+	definitions: Res<TowerDefinitions>,
+	mut cells: Query<&mut Cell>,
+	mut new_path: EventWriter<NewPathEvent>,
+) {
+	let save = match save {
+		Some(save) => save,
+		None => return,
+	};
+	if let Err(error) = save.check_geometry(&geometry) {
+		panic!("Invalid save: {}", error);
+	}
+
+	game.lives = save.lives;
+	game.level = save.level;
+	game.rocks_count = save.rocks_count;
+	game.chance_tier = save.chance_tier;
+	game.gold = save.gold;
+	history.pending_rolls = save.pending_rolls.clone();
+	rng.set_word_pos(save.word_pos);
+
+	for saved in save.cells.iter() {
+		let (x, y) = saved.position;
+		let mut cell = cells.get_mut(game.grid[y][x]).unwrap();
+		cell.content = match saved.content {
+			SavedContent::Empty => CellContent::Empty,
+			SavedContent::Limit => CellContent::Limit,
+			SavedContent::Start => CellContent::Start,
+			SavedContent::End => CellContent::End,
+			SavedContent::Rock => CellContent::Rock,
+			// Replaced by the tower entity below.
+			SavedContent::Tower => CellContent::Rock,
+			SavedContent::CheckPoint(index) => CellContent::CheckPoint(index),
+		};
+		graph.set_node_walkability(cell.node_id, saved.walkable);
+	}
+
+	for saved in save.towers.iter() {
+		let (x, y) = saved.position;
+		let cell_entity = game.grid[y][x];
+		let mut tower = Tower::new(
+			saved.kind.clone(),
+			saved.quality,
+			cell_entity,
+			saved.position,
+			&definitions,
+		);
+		tower.modifiers = saved.modifiers.clone();
+		tower.auras = saved.auras.clone();
+		for _ in 0..saved.level {
+			tower.level_up();
+		}
+		tower.experience = saved.experience;
+		tower.stats = saved.stats.clone();
+
+		let mut tower_commands = commands.spawn();
+		tower_commands
+			.insert(geometry.position_to_transform(x as f32, y as f32))
+			.insert(tower);
+		if saved.temporary {
+			tower_commands.insert(TemporaryTower);
+		}
+		cells.get_mut(cell_entity).unwrap().content = CellContent::Tower(tower_commands.id());
+	}
+
+	if graph.bfs() {
+		new_path.send(NewPathEvent(graph.path.to_vec()));
+	} else {
+		println!("The loaded save has no path from start to end.");
+	}
+	commands.remove_resource::<SaveGame>();
+}
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+	fn build(&self, app: &mut App) {
+		app.add_event::<SaveGameEvent>()
+			.add_startup_system_to_stage(StartupStage::PostStartup, load_game)
+			// Before any rock is placed, so a save never holds a tower that is not spawned yet.
+			.add_system(save_game_event_handler.before("place_rock"))
+			.add_system_set(SystemSet::on_enter(AppState::Build).with_system(autosave));
+	}
+}
@@ -0,0 +1,127 @@
+use crate::AppState;
+use bevy::ecs::schedule::ShouldRun;
+use bevy::prelude::*;
+use bevy::utils::Duration;
+
+const TICKS_PER_SECOND: u64 = 60;
+
+/// Runs the gameplay systems at a fixed rate, after `CoreStage::Update`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, StageLabel)]
+pub struct SimulationStage;
+
+/// Order of the gameplay systems within a tick, each step runs after the previous one.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub enum SimulationLabel {
+	Spawn,
+	Modifiers,
+	Poison,
+	Position,
+	Pathfinding,
+	Movement,
+	Cooldown,
+	Targeting,
+	Shoot,
+	Projectiles,
+	Death,
+	Experience,
+	Stats,
+	EndWave,
+}
+
+/// How many simulated seconds pass for each real second.
+pub struct TimeScale(pub u32);
+
+impl Default for TimeScale {
+	fn default() -> Self {
+		Self(1)
+	}
+}
+
+pub struct SimulationClock {
+	pub step: Duration,
+	pub tick: u64,
+	/// Runs one tick per frame whatever the frame time, used when there is no one watching.
+	pub unbounded: bool,
+	accumulator: Duration,
+	looping: bool,
+	held: bool,
+}
+
+impl Default for SimulationClock {
+	fn default() -> Self {
+		Self {
+			step: Duration::from_nanos(1_000_000_000 / TICKS_PER_SECOND),
+			tick: 0,
+			unbounded: false,
+			accumulator: Duration::ZERO,
+			looping: false,
+			held: false,
+		}
+	}
+}
+
+impl SimulationClock {
+	pub fn delta_seconds(&self) -> f32 {
+		self.step.as_secs_f32()
+	}
+
+	/// Skips the remaining ticks of this frame, so a state change is applied before the next tick.
+	pub fn hold(&mut self) {
+		self.held = true;
+	}
+}
+
+fn run_simulation(
+	time: Res<Time>,
+	time_scale: Res<TimeScale>,
+	app_state: Res<State<AppState>>,
+	mut clock: ResMut<SimulationClock>,
+) -> ShouldRun {
+	if *app_state.current() == AppState::Paused {
+		clock.looping = false;
+		return ShouldRun::No;
+	}
+
+	if !clock.looping {
+		clock.held = false;
+		if clock.unbounded {
+			clock.accumulator = clock.step;
+		} else {
+			clock.accumulator += time.delta() * time_scale.0;
+		}
+	}
+
+	if !clock.held && clock.accumulator >= clock.step {
+		let step = clock.step;
+		clock.accumulator -= step;
+		clock.tick += 1;
+		clock.looping = true;
+		ShouldRun::YesAndCheckAgain
+	} else {
+		clock.looping = false;
+		ShouldRun::No
+	}
+}
+
+/// Run criteria for the systems of the simulation stage that only run during a wave.
+pub fn enemies_phase(app_state: Res<State<AppState>>) -> ShouldRun {
+	if *app_state.current() == AppState::Enemies {
+		ShouldRun::Yes
+	} else {
+		ShouldRun::No
+	}
+}
+
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+	fn build(&self, app: &mut App) {
+		app.insert_resource(SimulationClock::default())
+			.insert_resource(TimeScale::default())
+			.add_stage_after(
+				CoreStage::Update,
+				SimulationStage,
+				SystemStage::parallel().with_run_criteria(run_simulation),
+			);
+	}
+}
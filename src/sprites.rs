@@ -0,0 +1,352 @@
+use crate::{
+	Cell, CellContent, ChainArcEvent, Game, Graph, GridGeometry, NewPathEvent, Projectile, Slime,
+	StatusKind, TemporaryTower, Tower, TowerKind, TowerLeveledUpEvent, TowersSwappedEvent,
+	UpdateRangeIndicatorScaleEvent,
+};
+use bevy::prelude::*;
+
+#[derive(Component)]
+pub struct SlimeUI {
+	slime: Entity,
+}
+
+#[derive(Component)]
+struct TemporaryTowerUI {
+	tower: Entity,
+}
+
+#[derive(Default)]
+struct TowerAtlasHandle {
+	pub handle: Option<Handle<TextureAtlas>>,
+}
+
+const SPRITESHEET_COLUMNS: usize = 8;
+
+/// The gems have a row per quality, the special towers use the rows after them.
+fn tower_sprite_index(tower: &Tower) -> usize {
+	match tower.kind {
+		TowerKind::Jade => 6 * SPRITESHEET_COLUMNS + 2,
+		TowerKind::Malachite => 8 * SPRITESHEET_COLUMNS + 2,
+		TowerKind::Silver => 7 * SPRITESHEET_COLUMNS + 6,
+		TowerKind::StarRuby => 6 * SPRITESHEET_COLUMNS + 1,
+		_ => tower.quality.to_usize() * SPRITESHEET_COLUMNS + tower.kind.to_usize(),
+	}
+}
+
+fn offset_ui_translation(mut translation: Vec3) -> Vec3 {
+	translation.z = 2.0;
+	translation.y += 20.0;
+	translation
+}
+
+fn init_spritesheet(
+	asset_server: Res<AssetServer>,
+	mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+	mut towers_atlas_handle: ResMut<TowerAtlasHandle>,
+) {
+	let texture_handle = asset_server.load("gems-spritesheet.png");
+	let texture_atlas = TextureAtlas::from_grid(
+		texture_handle,
+		Vec2::new(16.0, 16.0),
+		SPRITESHEET_COLUMNS,
+		9,
+	);
+	towers_atlas_handle.handle = Some(texture_atlases.add(texture_atlas));
+}
+
+/// The sprites are inserted at the end of the frame, after the first path was handled, so the
+/// cells of the current path are spawned already drawn.
+fn spawn_cell_sprites(
+	mut commands: Commands,
+	geometry: Res<GridGeometry>,
+	graph: Res<Graph>,
+	cells: Query<(Entity, &Cell, &Transform), Added<Cell>>,
+) {
+	for (entity, cell, transform) in cells.iter() {
+		let color = if graph.path.contains(&cell.node_id) {
+			Color::PURPLE
+		} else {
+			Color::BLACK
+		};
+		commands.entity(entity).insert_bundle(SpriteBundle {
+			sprite: Sprite {
+				color,
+				custom_size: Some(Vec2::new(geometry.tile_size, geometry.tile_size)),
+				..Default::default()
+			},
+			transform: *transform,
+			..Default::default()
+		});
+	}
+}
+
+fn update_cell_sprites(mut query: Query<(&Cell, &mut Sprite)>) {
+	for (cell, mut sprite) in query.iter_mut() {
+		match cell.content {
+			CellContent::Limit => sprite.color = Color::WHITE,
+			CellContent::Start => sprite.color = Color::GREEN,
+			CellContent::End => sprite.color = Color::RED,
+			CellContent::Rock => sprite.color = Color::GRAY,
+			CellContent::Tower(_) => sprite.color = Color::GRAY,
+			CellContent::Empty => {}
+			_ => sprite.color = Color::BLUE, // only checkpoints.
+		}
+	}
+}
+
+fn new_path_event_handle(
+	mut new_path: EventReader<NewPathEvent>,
+	graph: Res<Graph>,
+	game: Res<Game>,
+	mut query: Query<&mut Sprite>,
+) {
+	for event in new_path.iter() {
+		for mut sprite in query.iter_mut() {
+			if sprite.color == Color::PURPLE {
+				sprite.color = Color::BLACK;
+			}
+		}
+		for node_id in event.0.iter() {
+			let (x, y) = graph.get_node_position(*node_id).unwrap();
+			// The cells spawned this frame get their sprite at the end of it.
+			if let Ok(mut sprite) = query.get_mut(game.grid[y][x]) {
+				sprite.color = Color::PURPLE;
+			}
+		}
+	}
+}
+
+fn spawn_slime_sprites(
+	mut commands: Commands,
+	asset_server: Res<AssetServer>,
+	mut slimes: Query<(Entity, &Slime, &mut Transform), Added<Slime>>,
+) {
+	for (entity, slime, mut transform) in slimes.iter_mut() {
+		transform.scale = Vec3::new(3.0, 3.0, 1.0);
+		let texture: Handle<Image> = asset_server.load(slime.sprite.as_str());
+		commands.entity(entity).insert_bundle((
+			Sprite::default(),
+			texture,
+			Visibility::default(),
+			GlobalTransform::from(*transform),
+		));
+
+		commands
+			.spawn_bundle(SpriteBundle {
+				sprite: Sprite {
+					color: Color::rgb(0.1, 0.9, 0.1),
+					custom_size: Some(Vec2::new(30.0, 1.0)),
+					..Default::default()
+				},
+				transform: Transform::from_translation(offset_ui_translation(
+					transform.translation,
+				)),
+				..Default::default()
+			})
+			.insert(SlimeUI { slime: entity });
+	}
+}
+
+fn update_slime_ui(
+	mut commands: Commands,
+	slimes: Query<(&Transform, &Slime), Without<SlimeUI>>,
+	mut uis: Query<(Entity, &SlimeUI, &mut Transform)>,
+) {
+	for (entity, ui, mut transform) in uis.iter_mut() {
+		if let Ok((slime_transform, slime)) = slimes.get(ui.slime) {
+			transform.translation = offset_ui_translation(slime_transform.translation);
+			transform.scale = Vec3::new(slime.life as f32 / slime.max_life as f32, 1.0, 1.0);
+		} else {
+			commands.entity(entity).despawn_recursive();
+		}
+	}
+}
+
+fn update_slime_modifier_ui(mut query: Query<(&mut Sprite, &Slime)>) {
+	for (mut sprite, slime) in query.iter_mut() {
+		let mut mask = Color::rgb(1.0, 1.0, 1.0);
+		if slime.effects.has(StatusKind::Poison) {
+			mask.set_r(mask.r() * 0.2);
+			mask.set_b(mask.b() * 0.2);
+		}
+		if slime.effects.has(StatusKind::Slow) {
+			mask.set_r(mask.r() * 0.2);
+			mask.set_g(mask.g() * 0.2);
+		}
+		if slime.effects.has(StatusKind::Armor) {
+			mask.set_g(mask.g() * 0.2);
+			mask.set_b(mask.b() * 0.2);
+		}
+		sprite.color = mask;
+	}
+}
+
+fn spawn_tower_sprites(
+	mut commands: Commands,
+	mut update_range_scale: EventWriter<UpdateRangeIndicatorScaleEvent>,
+	towers_atlas_handle: Res<TowerAtlasHandle>,
+	asset_server: Res<AssetServer>,
+	mut towers: Query<(Entity, &Tower, &mut Transform, Option<&TemporaryTower>), Added<Tower>>,
+) {
+	if let Some(texture_atlas) = &towers_atlas_handle.handle {
+		for (entity, tower, mut transform, temporary) in towers.iter_mut() {
+			if temporary.is_some() {
+				commands
+					.spawn_bundle(SpriteBundle {
+						sprite: Sprite {
+							color: Color::rgb(0.9, 0.1, 0.1),
+							custom_size: Some(Vec2::new(30.0, 30.0)),
+							..Default::default()
+						},
+						transform: Transform::from_translation(transform.translation),
+						texture: asset_server.load("thick-circle.png"),
+						..Default::default()
+					})
+					.insert(TemporaryTowerUI { tower: entity });
+			}
+
+			transform.scale = Vec3::new(2.0, 2.0, 1.0);
+			commands.entity(entity).insert_bundle((
+				TextureAtlasSprite::new(tower_sprite_index(tower)),
+				texture_atlas.clone(),
+				Visibility::default(),
+				GlobalTransform::from(*transform),
+			));
+
+			update_range_scale.send(UpdateRangeIndicatorScaleEvent {
+				position: tower.position,
+				scale: Vec3::new(tower.range / 50.0, tower.range / 50.0, 1.0),
+			})
+		}
+	}
+}
+
+fn update_tower_range_scale(
+	mut leveled_up: EventReader<TowerLeveledUpEvent>,
+	mut towers_swapped: EventReader<TowersSwappedEvent>,
+	towers: Query<&Tower>,
+	mut update_range_scale: EventWriter<UpdateRangeIndicatorScaleEvent>,
+) {
+	let positions = leveled_up.iter().map(|event| event.position).chain(
+		towers_swapped
+			.iter()
+			.flat_map(|event| [event.from, event.to]),
+	);
+	for position in positions {
+		if let Some(tower) = towers.iter().find(|tower| tower.position == position) {
+			update_range_scale.send(UpdateRangeIndicatorScaleEvent {
+				position: tower.position,
+				scale: Vec3::new(tower.range / 50.0, tower.range / 50.0, 1.0),
+			});
+		}
+	}
+}
+
+fn despawn_temporary_tower_ui(
+	mut commands: Commands,
+	uis: Query<(Entity, &TemporaryTowerUI)>,
+	temporary_towers: Query<Entity, With<TemporaryTower>>,
+) {
+	for (entity, ui) in uis.iter() {
+		if temporary_towers.get(ui.tower).is_err() {
+			commands.entity(entity).despawn_recursive();
+		}
+	}
+}
+
+fn spawn_projectile_sprites(
+	mut commands: Commands,
+	asset_server: Res<AssetServer>,
+	projectiles: Query<(Entity, &Projectile, &Transform), Added<Projectile>>,
+) {
+	for (entity, projectile, transform) in projectiles.iter() {
+		let color = match projectile.kind {
+			TowerKind::Amethyst => Color::FUCHSIA,
+			TowerKind::Aquamarine => Color::AQUAMARINE,
+			TowerKind::Diamond => Color::SILVER,
+			TowerKind::Emerald => Color::DARK_GREEN,
+			TowerKind::Opal => Color::ANTIQUE_WHITE,
+			TowerKind::Ruby => Color::TOMATO,
+			TowerKind::Sapphire => Color::ALICE_BLUE,
+			TowerKind::Topaz => Color::GOLD,
+			TowerKind::Jade => Color::SEA_GREEN,
+			TowerKind::Malachite => Color::LIME_GREEN,
+			TowerKind::Silver => Color::GRAY,
+			TowerKind::StarRuby => Color::CRIMSON,
+		};
+		let texture: Handle<Image> = asset_server.load("projectile.png");
+
+		commands.entity(entity).insert_bundle((
+			Sprite {
+				color,
+				..Default::default()
+			},
+			texture,
+			Visibility::default(),
+			GlobalTransform::from(*transform),
+		));
+	}
+}
+
+/// A chain lightning arc, shown for a moment.
+#[derive(Component)]
+struct ChainArcSprite(Timer);
+
+fn spawn_chain_arc_sprites(mut commands: Commands, mut chain_arcs: EventReader<ChainArcEvent>) {
+	for arc in chain_arcs.iter() {
+		let delta = (arc.to - arc.from).truncate();
+		let mut transform = Transform::from_translation(offset_arc_translation(arc.from, arc.to));
+		transform.rotation = Quat::from_rotation_z(delta.y.atan2(delta.x));
+		commands
+			.spawn_bundle(SpriteBundle {
+				sprite: Sprite {
+					color: Color::CYAN,
+					custom_size: Some(Vec2::new(delta.length(), 3.0)),
+					..Default::default()
+				},
+				transform,
+				..Default::default()
+			})
+			.insert(ChainArcSprite(Timer::from_seconds(0.15, false)));
+	}
+}
+
+fn offset_arc_translation(from: Vec3, to: Vec3) -> Vec3 {
+	let mut translation = (from + to) / 2.0;
+	translation.z = 2.0;
+	translation
+}
+
+fn despawn_chain_arc_sprites(
+	mut commands: Commands,
+	time: Res<Time>,
+	mut arcs: Query<(Entity, &mut ChainArcSprite)>,
+) {
+	for (entity, mut arc) in arcs.iter_mut() {
+		if arc.0.tick(time.delta()).finished() {
+			commands.entity(entity).despawn_recursive();
+		}
+	}
+}
+
+/// Draws the simulation entities, nothing in here changes the state of the game.
+pub struct SpritesPlugin;
+
+impl Plugin for SpritesPlugin {
+	fn build(&self, app: &mut App) {
+		app.insert_resource(TowerAtlasHandle::default())
+			.add_startup_system(init_spritesheet)
+			.add_system(spawn_cell_sprites)
+			.add_system(update_cell_sprites)
+			.add_system(new_path_event_handle)
+			.add_system(spawn_slime_sprites)
+			.add_system(update_slime_ui)
+			.add_system(update_slime_modifier_ui)
+			.add_system(spawn_tower_sprites)
+			.add_system(update_tower_range_scale)
+			.add_system(despawn_temporary_tower_ui)
+			.add_system(spawn_projectile_sprites)
+			.add_system(spawn_chain_arc_sprites)
+			.add_system(despawn_chain_arc_sprites);
+	}
+}
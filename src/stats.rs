@@ -0,0 +1,172 @@
+use crate::{
+	enemies_phase, AppState, DamageEvent, DamageKind, SimulationLabel, SimulationStage,
+	SlimeKilledEvent, TemporaryTower, Tower,
+};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// What a tower did since it was placed.
+#[derive(std::cmp::PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct TowerStats {
+	pub physical_damage: u64,
+	pub magic_damage: u64,
+	pub pure_damage: u64,
+	pub kills: u32,
+	pub shots: u32,
+	pub crits: u32,
+	/// Simulation ticks spent in a wave, and the ones with at least one target in range.
+	pub wave_ticks: u64,
+	pub active_ticks: u64,
+}
+
+impl TowerStats {
+	pub fn damage(&self) -> u64 {
+		self.physical_damage + self.magic_damage + self.pure_damage
+	}
+
+	/// Percent of the wave time the tower had something to shoot at.
+	pub fn uptime(&self) -> f32 {
+		if self.wave_ticks == 0 {
+			return 0.0;
+		}
+		100.0 * self.active_ticks as f32 / self.wave_ticks as f32
+	}
+
+	pub fn summary(&self) -> String {
+		format!(
+			"Damage: {} ({} physical, {} magic, {} pure)\nKills: {}, shots: {}, crits: {}, uptime: {:.0}%",
+			self.damage(),
+			self.physical_damage,
+			self.magic_damage,
+			self.pure_damage,
+			self.kills,
+			self.shots,
+			self.crits,
+			self.uptime()
+		)
+	}
+}
+
+fn record_tower_stats(
+	mut damage_dealt: EventReader<DamageEvent>,
+	mut slime_killed: EventReader<SlimeKilledEvent>,
+	mut towers: Query<&mut Tower>,
+) {
+	for event in damage_dealt.iter() {
+		if let Ok(mut tower) = towers.get_mut(event.tower) {
+			let amount = event.amount as u64;
+			match event.kind {
+				DamageKind::Physical => tower.stats.physical_damage += amount,
+				DamageKind::Magic => tower.stats.magic_damage += amount,
+				DamageKind::Pure => tower.stats.pure_damage += amount,
+			}
+			if event.critical {
+				tower.stats.crits += 1;
+			}
+		}
+	}
+	for event in slime_killed.iter() {
+		if let Ok(mut tower) = towers.get_mut(event.tower) {
+			tower.stats.kills += 1;
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReportSort {
+	Damage,
+	Kills,
+	Shots,
+	Uptime,
+}
+
+pub struct ReportRow {
+	pub name: String,
+	pub position: (usize, usize),
+	pub stats: TowerStats,
+}
+
+/// The towers left on the board when the game ended, the best first.
+pub struct GameReport {
+	pub sort: ReportSort,
+	pub rows: Vec<ReportRow>,
+}
+
+impl Default for GameReport {
+	fn default() -> Self {
+		Self {
+			sort: ReportSort::Damage,
+			rows: vec![],
+		}
+	}
+}
+
+impl GameReport {
+	/// Ties are listed in board order, row by row, whatever the previous sort was.
+	pub fn sort_by(&mut self, sort: ReportSort) {
+		self.sort = sort;
+		self.rows.sort_by(|a, b| {
+			match sort {
+				ReportSort::Damage => b.stats.damage().cmp(&a.stats.damage()),
+				ReportSort::Kills => b.stats.kills.cmp(&a.stats.kills),
+				ReportSort::Shots => b.stats.shots.cmp(&a.stats.shots),
+				ReportSort::Uptime => b.stats.uptime().partial_cmp(&a.stats.uptime()).unwrap(),
+			}
+			.then_with(|| (a.position.1, a.position.0).cmp(&(b.position.1, b.position.0)))
+		});
+	}
+
+	pub fn lines(&self) -> Vec<String> {
+		self.rows
+			.iter()
+			.map(|row| {
+				format!(
+					"{} {:?}: {} damage, {} kills, {} shots, {:.0}% uptime",
+					row.name,
+					row.position,
+					row.stats.damage(),
+					row.stats.kills,
+					row.stats.shots,
+					row.stats.uptime()
+				)
+			})
+			.collect()
+	}
+}
+
+fn build_game_report(
+	mut report: ResMut<GameReport>,
+	towers: Query<&Tower, Without<TemporaryTower>>,
+) {
+	report.rows = towers
+		.iter()
+		.map(|tower| ReportRow {
+			name: format!("{:?} {:?}", tower.quality, tower.kind),
+			position: tower.position,
+			stats: tower.stats.clone(),
+		})
+		.collect();
+	report.sort_by(ReportSort::Damage);
+}
+
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+	fn build(&self, app: &mut App) {
+		app.init_resource::<GameReport>()
+			.add_system_set_to_stage(
+				SimulationStage,
+				SystemSet::new()
+					.with_run_criteria(enemies_phase)
+					.with_system(
+						record_tower_stats
+							.label(SimulationLabel::Stats)
+							.after(SimulationLabel::Experience),
+					),
+			)
+			.add_system_set(
+				SystemSet::on_enter(AppState::GameOver)
+					.with_system(build_game_report.label("game_report")),
+			);
+	}
+}
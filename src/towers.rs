@@ -1,18 +1,27 @@
 use crate::{
-	flat_distance, position_to_transform, AppState, Cell, CellContent, Cleave, RockPlacedEvent,
-	TowerAuras, TowerModifier, UpdateRangeIndicatorScaleEvent, UpdateTowerTooltipEvent,
+	asset_path, enemies_phase, flat_distance, AppState, AttackType, BuildHistory, Cell,
+	CellContent, Game, GameRng, GridGeometry, QualityChances, RecipeBuiltEvent, RockPlacedEvent,
+	SimulationLabel, SimulationStage, SlimeKilledEvent, TowerAuras, TowerModifier, TowerStats,
+	UpdateTowerTooltipEvent,
 };
 use bevy::prelude::*;
 use bevy::utils::Duration;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
 
 const RANGE_SCALE: f32 = 4.0;
+const MAX_TOWER_LEVEL: u8 = 5;
+/// Gold paid to swap two placed towers.
+pub const SWAP_COST: u32 = 5;
 
 fn scale_range(range: f32) -> f32 {
 	range / RANGE_SCALE
 }
 
-#[derive(std::cmp::PartialEq, Clone, Debug)]
+#[derive(std::cmp::PartialEq, Eq, Hash, Clone, Debug, Serialize, Deserialize)]
 pub enum TowerKind {
 	Amethyst,
 	Aquamarine,
@@ -22,10 +31,18 @@ pub enum TowerKind {
 	Ruby,
 	Sapphire,
 	Topaz,
+	Jade,
+	Malachite,
+	Silver,
+	StarRuby,
 }
 
+/// The kinds rolled when a rock is placed, the others are only built from recipes.
+const GEM_KINDS: usize = 8;
+const TOWER_KINDS: usize = 12;
+
 impl TowerKind {
-	fn from_usize(value: usize) -> Self {
+	pub fn from_usize(value: usize) -> Self {
 		match value {
 			0 => Self::Diamond,
 			1 => Self::Ruby,
@@ -35,14 +52,103 @@ impl TowerKind {
 			5 => Self::Sapphire,
 			6 => Self::Opal,
 			7 => Self::Aquamarine,
+			8 => Self::Silver,
+			9 => Self::Malachite,
+			10 => Self::StarRuby,
+			11 => Self::Jade,
 			_ => panic!("Not a tower kind."),
 		}
 	}
+
+	pub fn to_usize(&self) -> usize {
+		match self {
+			Self::Diamond => 0,
+			Self::Ruby => 1,
+			Self::Emerald => 2,
+			Self::Topaz => 3,
+			Self::Amethyst => 4,
+			Self::Sapphire => 5,
+			Self::Opal => 6,
+			Self::Aquamarine => 7,
+			Self::Silver => 8,
+			Self::Malachite => 9,
+			Self::StarRuby => 10,
+			Self::Jade => 11,
+		}
+	}
+
+	pub fn is_gem(&self) -> bool {
+		self.to_usize() < GEM_KINDS
+	}
+}
+
+/// The higher the quality the stronger the gem, it is also the row of the gem in the spritesheet.
+#[derive(
+	std::cmp::PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug, Serialize, Deserialize,
+)]
+pub enum TowerQuality {
+	Chipped,
+	Flawed,
+	Normal,
+	Flawless,
+	Perfect,
+	Great,
+}
+
+impl TowerQuality {
+	pub fn from_usize(value: usize) -> Self {
+		match value {
+			0 => Self::Chipped,
+			1 => Self::Flawed,
+			2 => Self::Normal,
+			3 => Self::Flawless,
+			4 => Self::Perfect,
+			5 => Self::Great,
+			_ => panic!("Not a tower quality."),
+		}
+	}
+
+	pub fn to_usize(self) -> usize {
+		match self {
+			Self::Chipped => 0,
+			Self::Flawed => 1,
+			Self::Normal => 2,
+			Self::Flawless => 3,
+			Self::Perfect => 4,
+			Self::Great => 5,
+		}
+	}
+
+	fn damage_multiplier(self) -> f32 {
+		match self {
+			Self::Chipped => 1.0,
+			Self::Flawed => 2.0,
+			Self::Normal => 4.0,
+			Self::Flawless => 7.0,
+			Self::Perfect => 12.0,
+			Self::Great => 20.0,
+		}
+	}
+
+	fn range_multiplier(self) -> f32 {
+		1.0 + 0.05 * self.to_usize() as f32
+	}
+
+	/// `None` when there is no quality that high.
+	pub fn upgraded(self, steps: usize) -> Option<Self> {
+		let value = self.to_usize() + steps;
+		if value <= Self::Great.to_usize() {
+			Some(Self::from_usize(value))
+		} else {
+			None
+		}
+	}
 }
 
 #[derive(std::cmp::PartialEq, Clone, Component)]
 pub struct Tower {
 	pub kind: TowerKind,
+	pub quality: TowerQuality,
 	pub cell: Entity,
 	pub position: (usize, usize),
 	pub range: f32,
@@ -50,13 +156,55 @@ pub struct Tower {
 	pub attack_speed: f32,
 	pub targets: Vec<Entity>,
 	pub damage: f32,
+	pub attack_type: AttackType,
 	pub tooltip: String,
 	pub modifiers: TowerModifier,
 	pub auras: TowerAuras,
 	pub recieved_auras: TowerModifier,
+	pub level: u8,
+	/// Kills since the last level.
+	pub experience: u32,
+	pub stats: TowerStats,
 }
 
 impl Tower {
+	/// Kills needed to reach the next level, `None` at the last level.
+	pub fn experience_to_level(&self) -> Option<u32> {
+		if self.level < MAX_TOWER_LEVEL {
+			Some(5 * (self.level as u32 + 1))
+		} else {
+			None
+		}
+	}
+
+	/// Each level gives 10% more damage, 2.5% more range and 10 attack speed.
+	pub fn level_up(&mut self) {
+		self.level += 1;
+		self.damage *= 1.1;
+		self.range *= 1.025;
+		self.attack_speed += 10.0;
+	}
+
+	/// Returns `true` when the tower reached a new level.
+	fn gain_experience(&mut self, amount: u32) -> bool {
+		let mut leveled_up = false;
+		self.experience += amount;
+		while let Some(needed) = self.experience_to_level() {
+			if self.experience < needed {
+				break;
+			}
+			self.experience -= needed;
+			self.level_up();
+			leveled_up = true;
+		}
+		leveled_up
+	}
+
+	/// Gold given back when the tower is sold.
+	pub fn refund(&self) -> u32 {
+		5 * (self.quality.to_usize() as u32 + 1)
+	}
+
 	pub fn get_attack_speed(&self) -> f32 {
 		let mut attack_speed = self.attack_speed;
 		for modifier in self.modifiers.attack_speed.iter() {
@@ -68,184 +216,475 @@ impl Tower {
 		attack_speed
 	}
 
-	fn new(kind: TowerKind, cell: Entity, position: (usize, usize)) -> Self {
-		let mut tower = Self {
+	pub fn new(
+		kind: TowerKind,
+		quality: TowerQuality,
+		cell: Entity,
+		position: (usize, usize),
+		definitions: &TowerDefinitions,
+	) -> Self {
+		let definition = definitions.get(&kind);
+		let mut modifiers = definition.modifiers.clone();
+		if let Some(cleave) = &mut modifiers.cleave {
+			cleave.range = scale_range(cleave.range);
+		}
+		if let Some(chain) = &mut modifiers.chain {
+			chain.range = scale_range(chain.range);
+		}
+		let mut auras = definition.auras.clone();
+		for (_, range) in auras.attack_speed.iter_mut() {
+			*range = scale_range(*range);
+		}
+
+		Self {
 			kind,
+			quality,
 			cell,
 			position,
-			range: 0.0,
+			range: scale_range(definition.range) * quality.range_multiplier(),
 			cooldown: Duration::from_secs(0),
-			attack_speed: 0.0,
+			attack_speed: definition.attack_speed,
 			targets: vec![],
-			damage: 0.0,
-			modifiers: TowerModifier::default(),
-			auras: TowerAuras::default(),
+			damage: definition.damage * quality.damage_multiplier(),
+			attack_type: definition.attack_type,
+			modifiers,
+			auras,
 			recieved_auras: TowerModifier::default(),
-			tooltip: String::new(),
-		};
-		match tower.kind {
-			TowerKind::Amethyst => {
-				tower.range = scale_range(500.0);
-				tower.damage = 2.0;
-				tower.attack_speed = 283.0;
-				tower
-					.modifiers
-					.apply_armor
-					.insert(-2, Duration::from_secs(5));
-				tower.tooltip = "Decrease enemy's armor by 2.".to_string();
-			}
-			TowerKind::Aquamarine => {
-				tower.range = scale_range(400.0);
-				tower.damage = 2.0;
-				tower.attack_speed = 367.0;
-				tower.modifiers.attack_speed.push(200.0);
-				tower.tooltip = "+ 200 attack speed.".to_string();
+			tooltip: definition.tooltip.clone(),
+			level: 0,
+			experience: 0,
+			stats: TowerStats::default(),
+		}
+	}
+}
+
+fn is_positive(value: f32) -> bool {
+	value.is_finite() && value > 0.0
+}
+
+/// Stats of a tower kind, the ranges are written before `scale_range`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TowerDefinition {
+	pub range: f32,
+	pub damage: f32,
+	pub attack_type: AttackType,
+	pub attack_speed: f32,
+	#[serde(default)]
+	pub modifiers: TowerModifier,
+	#[serde(default)]
+	pub auras: TowerAuras,
+	#[serde(default)]
+	pub tooltip: String,
+}
+
+impl TowerDefinition {
+	fn validate(&self) -> Result<(), String> {
+		if !is_positive(self.range) {
+			return Err(format!("range must be positive, got {}", self.range));
+		}
+		if !self.damage.is_finite() || self.damage < 0.0 {
+			return Err(format!("damage can not be negative, got {}", self.damage));
+		}
+		if !is_positive(self.attack_speed) {
+			return Err(format!(
+				"attack_speed must be positive, got {}",
+				self.attack_speed
+			));
+		}
+		if let Some(cleave) = &self.modifiers.cleave {
+			if !is_positive(cleave.range) {
+				return Err(format!(
+					"cleave range must be positive, got {}",
+					cleave.range
+				));
 			}
-			TowerKind::Diamond => {
-				tower.range = scale_range(500.0);
-				tower.damage = 5.0;
-				tower.attack_speed = 170.0;
+			if !(0.0..=1.0).contains(&cleave.damage) {
+				return Err(format!(
+					"cleave damage is a ratio between 0 and 1, got {}",
+					cleave.damage
+				));
 			}
-			TowerKind::Emerald => {
-				tower.range = scale_range(500.0);
-				tower.damage = 2.0;
-				tower.attack_speed = 170.0;
-				tower
-					.modifiers
-					.apply_poison
-					.insert(2, Duration::from_secs(5));
-				tower.tooltip = "Enemies take 2 damage per second.\nLasts 5 seconds.".to_string();
+		}
+		if let Some(chain) = &self.modifiers.chain {
+			if !is_positive(chain.range) {
+				return Err(format!("chain range must be positive, got {}", chain.range));
 			}
-			TowerKind::Opal => {
-				tower.range = scale_range(500.0);
-				tower.damage = 1.0;
-				tower.attack_speed = 170.0;
-				tower.auras.attack_speed.push((20.0, scale_range(500.0)));
-				tower.tooltip = "Increases allies attack speed by 20.".to_string();
+			if chain.bounces == 0 {
+				return Err("chain bounces must be at least 1".to_string());
 			}
-			TowerKind::Ruby => {
-				tower.range = scale_range(500.0);
-				tower.damage = 4.0;
-				tower.attack_speed = 170.0;
-				tower.modifiers.cleave = Some(Cleave {
-					range: scale_range(300.0),
-					damage: 0.3,
-				})
+			if !(chain.falloff > 0.0 && chain.falloff <= 1.0) {
+				return Err(format!(
+					"chain falloff is a ratio between 0 and 1, got {}",
+					chain.falloff
+				));
 			}
-			TowerKind::Sapphire => {
-				tower.range = scale_range(600.0);
-				tower.damage = 2.0;
-				tower.attack_speed = 170.0;
-				tower
-					.modifiers
-					.apply_speed
-					.insert(30, Duration::from_secs(5));
-				tower.tooltip = "Decreases enemy's movement speed by 30%.".to_string();
+		}
+		if !(0.0..=1.0).contains(&self.modifiers.crit_chance) {
+			return Err(format!(
+				"crit_chance is a ratio between 0 and 1, got {}",
+				self.modifiers.crit_chance
+			));
+		}
+		if self.modifiers.crit_chance > 0.0
+			&& !(self.modifiers.crit_multiplier.is_finite()
+				&& self.modifiers.crit_multiplier >= 1.0)
+		{
+			return Err(format!(
+				"crit_multiplier must be at least 1, got {}",
+				self.modifiers.crit_multiplier
+			));
+		}
+		if self.modifiers.target_count == Some(0) {
+			return Err("target_count must be at least 1".to_string());
+		}
+		for (_, range) in self.auras.attack_speed.iter() {
+			if !is_positive(*range) {
+				return Err(format!("aura range must be positive, got {}", range));
 			}
-			TowerKind::Topaz => {
-				tower.range = scale_range(600.0);
-				tower.damage = 3.0;
-				tower.attack_speed = 131.0;
-				tower.modifiers.target_count = Some(3);
-				tower.tooltip = "Attacks up to 3 enemy's at the same time.".to_string();
+		}
+		Ok(())
+	}
+}
+
+/// The stats of every tower kind, the gems and the recipes, read from `assets/towers.ron`.
+pub struct TowerDefinitions(HashMap<TowerKind, TowerDefinition>);
+
+impl TowerDefinitions {
+	pub fn load(path: &Path) -> Result<Self, String> {
+		let content = fs::read_to_string(path).map_err(|error| error.to_string())?;
+		let definitions: HashMap<TowerKind, TowerDefinition> =
+			ron::from_str(&content).map_err(|error| error.to_string())?;
+
+		for value in 0..TOWER_KINDS {
+			let kind = TowerKind::from_usize(value);
+			match definitions.get(&kind) {
+				Some(definition) => definition
+					.validate()
+					.map_err(|error| format!("{:?}: {}", kind, error))?,
+				None => return Err(format!("{:?} is not defined", kind)),
 			}
-		};
-		tower
+		}
+		Ok(Self(definitions))
+	}
+
+	pub fn get(&self, kind: &TowerKind) -> &TowerDefinition {
+		&self.0[kind]
 	}
 }
 
 #[derive(Component)]
-pub struct TemporaryTower {
-	pub ui: Entity,
-}
+pub struct TemporaryTower;
 
 pub struct TowersPlugin;
 
-#[derive(Default)]
-struct TowerAtlasHandle {
-	pub handle: Option<Handle<TextureAtlas>>,
+/// Asks to keep the temporary tower at `position`, the other ones become rocks.
+pub struct KeepTowerEvent {
+	pub position: (usize, usize),
+}
+
+pub struct TowerKeptEvent {
+	pub position: (usize, usize),
+}
+
+/// Asks to merge the temporary tower at `position` with its identical candidates.
+pub struct CombineTowersEvent {
+	pub position: (usize, usize),
+}
+
+pub struct TowersCombinedEvent {
+	pub position: (usize, usize),
+}
+
+/// Asks to exchange the places of the two placed towers at `from` and `to`.
+pub struct SwapTowersEvent {
+	pub from: (usize, usize),
+	pub to: (usize, usize),
+}
+
+pub struct TowersSwappedEvent {
+	pub from: (usize, usize),
+	pub to: (usize, usize),
+}
+
+pub struct TowerLeveledUpEvent {
+	pub position: (usize, usize),
+}
+
+/// Two identical candidates give one quality more, four give two.
+pub fn combine_steps(identical: usize) -> usize {
+	match identical {
+		0 | 1 => 0,
+		2 | 3 => 1,
+		_ => 2,
+	}
 }
 
+/// Number of candidates with the kind and quality of `tower`, itself included.
+pub fn count_identical<'a>(candidates: impl Iterator<Item = &'a Tower>, tower: &Tower) -> usize {
+	candidates
+		.filter(|other| other.kind == tower.kind && other.quality == tower.quality)
+		.count()
+}
+
+/// Ends the Select phase, the candidates other than the one at `position` become rocks.
+fn turn_candidates_into_rocks(
+	commands: &mut Commands,
+	game: &Game,
+	candidates: &Query<(Entity, &Tower), With<TemporaryTower>>,
+	cells: &mut Query<&mut Cell>,
+	position: (usize, usize),
+) {
+	for (entity, tower) in candidates.iter() {
+		if tower.position != position {
+			let (tx, ty) = tower.position;
+			cells.get_mut(game.grid[ty][tx]).unwrap().content = CellContent::Rock;
+			commands.entity(entity).despawn_recursive();
+		}
+	}
+}
+
+#[allow(clippy::too_many_arguments)]
 fn spawn_tower_event_handler(
 	mut commands: Commands,
 	mut rock_placed: EventReader<RockPlacedEvent>,
-	mut update_range_scale: EventWriter<UpdateRangeIndicatorScaleEvent>,
-	towers_atlas_handle: Res<TowerAtlasHandle>,
-	asset_server: Res<AssetServer>,
+	mut rng: ResMut<GameRng>,
+	game: Res<Game>,
+	mut history: ResMut<BuildHistory>,
+	chances: Res<QualityChances>,
+	definitions: Res<TowerDefinitions>,
+	geometry: Res<GridGeometry>,
 	mut cells: Query<&mut Cell>,
 ) {
-	if let Some(texture_atlas) = &towers_atlas_handle.handle {
-		let mut rng = rand::thread_rng();
-
-		for rock in rock_placed.iter() {
-			let mut cell = cells.get_mut(rock.entity).unwrap();
-			let index = rng.gen_range(0..8);
-			let kind = TowerKind::from_usize(index);
-
-			let transform = position_to_transform(cell.position.0 as f32, cell.position.1 as f32);
-			let tower = Tower::new(kind, rock.entity, cell.position);
-
-			let temporary_tower_ui = commands
-				.spawn_bundle(SpriteBundle {
-					sprite: Sprite {
-						color: Color::rgb(0.9, 0.1, 0.1),
-						custom_size: Some(Vec2::new(30.0, 30.0)),
-						..Default::default()
-					},
-					transform,
-					texture: asset_server.load("thick-circle.png"),
-					..Default::default()
-				})
-				.id();
-
-			let tower_id = commands
-				.spawn_bundle(SpriteSheetBundle {
-					texture_atlas: texture_atlas.clone(),
-					transform: transform.with_scale(Vec3::new(2.0, 2.0, 1.0)),
-					sprite: TextureAtlasSprite {
-						index,
-						..Default::default()
-					},
-					..Default::default()
-				})
-				.insert(tower.clone())
-				.insert(TemporaryTower {
-					ui: temporary_tower_ui,
-				})
-				.id();
-
-			cell.content = CellContent::Tower(tower_id);
-
-			update_range_scale.send(UpdateRangeIndicatorScaleEvent {
-				position: cell.position,
-				scale: Vec3::new(tower.range / 50.0, tower.range / 50.0, 1.0),
-			})
+	for rock in rock_placed.iter() {
+		let mut cell = cells.get_mut(rock.entity).unwrap();
+		let (kind, quality) = match history.pending_rolls.pop() {
+			Some(roll) => roll,
+			None => (
+				TowerKind::from_usize(rng.gen_range(0..GEM_KINDS)),
+				chances.roll(game.chance_tier, &mut *rng),
+			),
+		};
+
+		let transform =
+			geometry.position_to_transform(cell.position.0 as f32, cell.position.1 as f32);
+		let tower = Tower::new(kind, quality, rock.entity, cell.position, &definitions);
+
+		let tower_id = commands
+			.spawn()
+			.insert(transform)
+			.insert(tower)
+			.insert(TemporaryTower)
+			.id();
+
+		cell.content = CellContent::Tower(tower_id);
+	}
+}
+
+#[allow(clippy::too_many_arguments)]
+fn keep_tower_event_handler(
+	mut commands: Commands,
+	mut keep_tower: EventReader<KeepTowerEvent>,
+	game: Res<Game>,
+	temporary_towers: Query<(Entity, &Tower), With<TemporaryTower>>,
+	mut cells: Query<&mut Cell>,
+	mut app_state: ResMut<State<AppState>>,
+	mut towers_combined: EventReader<TowersCombinedEvent>,
+	mut recipe_built: EventReader<RecipeBuiltEvent>,
+	mut tower_kept: EventWriter<TowerKeptEvent>,
+) {
+	// The round already ended with a combination or a recipe.
+	if towers_combined.iter().count() + recipe_built.iter().count() > 0 {
+		keep_tower.iter().for_each(drop);
+		return;
+	}
+
+	for event in keep_tower.iter() {
+		if !temporary_towers
+			.iter()
+			.any(|(_, tower)| tower.position == event.position)
+		{
+			continue;
+		}
+
+		for (entity, tower) in temporary_towers.iter() {
+			if tower.position == event.position {
+				commands.entity(entity).remove::<TemporaryTower>();
+			}
 		}
+		turn_candidates_into_rocks(
+			&mut commands,
+			&game,
+			&temporary_towers,
+			&mut cells,
+			event.position,
+		);
+		tower_kept.send(TowerKeptEvent {
+			position: event.position,
+		});
+		app_state.set(AppState::Enemies).unwrap();
+		return;
 	}
 }
 
-fn init_spritesheet(
-	asset_server: Res<AssetServer>,
-	mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-	mut towers_atlas_handle: ResMut<TowerAtlasHandle>,
+#[allow(clippy::too_many_arguments)]
+fn combine_towers_event_handler(
+	mut commands: Commands,
+	mut combine_towers: EventReader<CombineTowersEvent>,
+	game: Res<Game>,
+	definitions: Res<TowerDefinitions>,
+	geometry: Res<GridGeometry>,
+	temporary_towers: Query<(Entity, &Tower), With<TemporaryTower>>,
+	mut cells: Query<&mut Cell>,
+	mut app_state: ResMut<State<AppState>>,
+	mut towers_combined: EventWriter<TowersCombinedEvent>,
 ) {
-	let texture_handle = asset_server.load("gems-spritesheet.png");
-	let texture_atlas = TextureAtlas::from_grid(texture_handle, Vec2::new(16.0, 16.0), 8, 9);
-	towers_atlas_handle.handle = Some(texture_atlases.add(texture_atlas));
+	for event in combine_towers.iter() {
+		let (entity, chosen) = match temporary_towers
+			.iter()
+			.find(|(_, tower)| tower.position == event.position)
+		{
+			Some(candidate) => candidate,
+			None => continue,
+		};
+		let identical = count_identical(temporary_towers.iter().map(|(_, tower)| tower), chosen);
+		let upgraded = chosen
+			.quality
+			.upgraded(combine_steps(identical))
+			.or_else(|| chosen.quality.upgraded(1));
+		let quality = match upgraded {
+			Some(quality) if identical > 1 => quality,
+			_ => continue,
+		};
+
+		let (x, y) = chosen.position;
+		let tower = Tower::new(
+			chosen.kind.clone(),
+			quality,
+			chosen.cell,
+			chosen.position,
+			&definitions,
+		);
+		let tower_id = commands
+			.spawn()
+			.insert(geometry.position_to_transform(x as f32, y as f32))
+			.insert(tower)
+			.id();
+		commands.entity(entity).despawn_recursive();
+		cells.get_mut(game.grid[y][x]).unwrap().content = CellContent::Tower(tower_id);
+
+		turn_candidates_into_rocks(
+			&mut commands,
+			&game,
+			&temporary_towers,
+			&mut cells,
+			event.position,
+		);
+		towers_combined.send(TowersCombinedEvent {
+			position: event.position,
+		});
+		app_state.set(AppState::Enemies).unwrap();
+		return;
+	}
 }
 
-fn clear_auras(mut query: Query<&mut Tower>) {
-	for mut tower in query.iter_mut() {
-		tower.recieved_auras = TowerModifier::default();
+/// One experience per kill, the killed slimes of sold towers are lost.
+fn gain_experience(
+	mut slime_killed: EventReader<SlimeKilledEvent>,
+	mut towers: Query<&mut Tower>,
+	mut leveled_up: EventWriter<TowerLeveledUpEvent>,
+) {
+	for event in slime_killed.iter() {
+		if let Ok(mut tower) = towers.get_mut(event.tower) {
+			if tower.gain_experience(1) {
+				leveled_up.send(TowerLeveledUpEvent {
+					position: tower.position,
+				});
+			}
+		}
+	}
+}
+
+fn swap_towers_event_handler(
+	mut swap_towers: EventReader<SwapTowersEvent>,
+	mut game: ResMut<Game>,
+	geometry: Res<GridGeometry>,
+	mut cells: Query<&mut Cell>,
+	mut towers: Query<(&mut Tower, &mut Transform), Without<TemporaryTower>>,
+	mut towers_swapped: EventWriter<TowersSwappedEvent>,
+) {
+	for event in swap_towers.iter() {
+		if event.from == event.to {
+			continue;
+		}
+		let cell_entities = [event.from, event.to].map(|(x, y)| {
+			game.grid
+				.get(y)
+				.and_then(|row| row.get(x))
+				.map(|entity| (*entity, cells.get(*entity).unwrap().content.clone()))
+		});
+		let (from_cell, from_tower, to_cell, to_tower) = match cell_entities {
+			[Some((from_cell, CellContent::Tower(from_tower))), Some((to_cell, CellContent::Tower(to_tower)))]
+				if towers.get(from_tower).is_ok() && towers.get(to_tower).is_ok() =>
+			{
+				(from_cell, from_tower, to_cell, to_tower)
+			}
+			_ => continue,
+		};
+		if !game.spend_gold(SWAP_COST) {
+			continue;
+		}
+
+		for (tower_entity, cell_entity, (x, y)) in [
+			(from_tower, to_cell, event.to),
+			(to_tower, from_cell, event.from),
+		] {
+			let (mut tower, mut transform) = towers.get_mut(tower_entity).unwrap();
+			tower.cell = cell_entity;
+			tower.position = (x, y);
+			transform.translation = geometry.position_to_translation(x as f32, y as f32);
+			cells.get_mut(cell_entity).unwrap().content = CellContent::Tower(tower_entity);
+		}
+		towers_swapped.send(TowersSwappedEvent {
+			from: event.from,
+			to: event.to,
+		});
+	}
+}
+
+/// The auras of the towers that moved are given again right away, not at the next wave.
+fn refresh_swapped_auras(
+	mut towers_swapped: EventReader<TowersSwappedEvent>,
+	mut towers: Query<(&mut Tower, &Transform, Option<&TemporaryTower>)>,
+	mut update_ui: EventWriter<UpdateTowerTooltipEvent>,
+) {
+	if towers_swapped.iter().count() > 0 {
+		apply_auras(&mut towers, &mut update_ui);
 	}
 }
 
-fn set_auras(
-	mut query: Query<(&mut Tower, &Transform)>,
+fn update_auras(
+	mut towers: Query<(&mut Tower, &Transform, Option<&TemporaryTower>)>,
 	mut update_ui: EventWriter<UpdateTowerTooltipEvent>,
 ) {
+	apply_auras(&mut towers, &mut update_ui);
+}
+
+fn apply_auras(
+	query: &mut Query<(&mut Tower, &Transform, Option<&TemporaryTower>)>,
+	update_ui: &mut EventWriter<UpdateTowerTooltipEvent>,
+) {
+	for (mut tower, _, _) in query.iter_mut() {
+		tower.recieved_auras = TowerModifier::default();
+	}
+
+	// Filtered in the loop, `iter_combinations_mut` needs a query without a `Without` filter.
 	let mut combinations = query.iter_combinations_mut();
-	while let Some([(mut a, at), (mut b, bt)]) = combinations.fetch_next() {
+	while let Some([(mut a, at, a_temporary), (mut b, bt, b_temporary)]) = combinations.fetch_next()
+	{
+		if a_temporary.is_some() || b_temporary.is_some() {
+			continue;
+		}
 		// TODO refactor this with a function
 		for (value, range) in a.auras.attack_speed.iter() {
 			if flat_distance(*at, *bt) <= *range {
@@ -268,15 +707,53 @@ fn set_auras(
 
 impl Plugin for TowersPlugin {
 	fn build(&self, app: &mut App) {
-		app.insert_resource(TowerAtlasHandle::default())
-			.add_startup_system(init_spritesheet)
+		let path = asset_path("towers.ron");
+		let definitions = TowerDefinitions::load(&path).unwrap_or_else(|error| {
+			panic!("Invalid tower definitions {}: {}", path.display(), error)
+		});
+
+		app.insert_resource(definitions)
+			.add_event::<KeepTowerEvent>()
+			.add_event::<TowerKeptEvent>()
+			.add_event::<CombineTowersEvent>()
+			.add_event::<TowersCombinedEvent>()
+			.add_event::<SwapTowersEvent>()
+			.add_event::<TowersSwappedEvent>()
+			.add_event::<TowerLeveledUpEvent>()
 			.add_system_set(
-				SystemSet::on_update(AppState::Build).with_system(spawn_tower_event_handler),
+				SystemSet::on_update(AppState::Build)
+					.with_system(
+						spawn_tower_event_handler
+							.label("spawn_tower")
+							.after("place_rock"),
+					)
+					.with_system(
+						swap_towers_event_handler
+							.label("swap_towers")
+							.after("clear_cell")
+							.before("undo_rock"),
+					)
+					.with_system(refresh_swapped_auras.after("swap_towers")),
 			)
 			.add_system_set(
-				SystemSet::on_enter(AppState::Enemies)
-					.with_system(clear_auras.before("set_auras"))
-					.with_system(set_auras.label("set_auras")),
-			);
+				SystemSet::on_update(AppState::Select)
+					.with_system(combine_towers_event_handler.label("combine_towers"))
+					.with_system(
+						keep_tower_event_handler
+							.label("keep_tower")
+							.after("build_recipe"),
+					),
+			)
+			.add_system_set_to_stage(
+				SimulationStage,
+				SystemSet::new()
+					.with_run_criteria(enemies_phase)
+					.with_system(
+						gain_experience
+							.label(SimulationLabel::Experience)
+							.after(SimulationLabel::Death),
+					),
+			)
+			.add_system_set(SystemSet::on_enter(AppState::Enemies).with_system(update_auras));
 	}
 }
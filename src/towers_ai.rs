@@ -1,4 +1,7 @@
-use crate::{flat_distance, AppState, Projectile, Slime, TemporaryTower, Tower};
+use crate::{
+	enemies_phase, flat_distance, Projectile, SimulationClock, SimulationLabel, SimulationStage,
+	Slime, TemporaryTower, Tower,
+};
 use bevy::prelude::*;
 use bevy::utils::Duration;
 
@@ -25,11 +28,18 @@ fn towers_targeting(
 				tower.targets.push(*slime_entity);
 			}
 		}
+		tower.stats.wave_ticks += 1;
+		if !tower.targets.is_empty() {
+			tower.stats.active_ticks += 1;
+		}
 	}
 }
 
-fn towers_cooldown(time: Res<Time>, mut towers: Query<&mut Tower, Without<TemporaryTower>>) {
-	let time_delta = time.delta();
+fn towers_cooldown(
+	clock: Res<SimulationClock>,
+	mut towers: Query<&mut Tower, Without<TemporaryTower>>,
+) {
+	let time_delta = clock.step;
 	for mut tower in towers.iter_mut() {
 		if tower.cooldown < time_delta {
 			tower.cooldown = Duration::ZERO;
@@ -41,56 +51,23 @@ fn towers_cooldown(time: Res<Time>, mut towers: Query<&mut Tower, Without<Tempor
 
 fn towers_shoot(
 	mut commands: Commands,
-	mut towers: Query<(&mut Tower, &Transform), Without<TemporaryTower>>,
-	mut slimes: Query<(&Transform, &mut Slime)>,
-	asset_server: Res<AssetServer>,
+	mut towers: Query<(Entity, &mut Tower, &Transform), Without<TemporaryTower>>,
+	slimes: Query<&Transform, With<Slime>>,
 ) {
-	for (mut tower, transform) in towers.iter_mut() {
+	for (entity, mut tower, transform) in towers.iter_mut() {
 		if tower.cooldown == Duration::ZERO && !tower.targets.is_empty() {
 			tower.cooldown = attack_speed(tower.get_attack_speed());
 			for target in tower.targets.clone().iter() {
-				if let Ok((slime_transform, mut slime)) = slimes.get_mut(*target) {
+				if let Ok(slime_transform) = slimes.get(*target) {
 					let projectile_bundle = Projectile::new_bundle(
-						asset_server.load("projectile.png"),
 						transform,
+						entity,
 						tower.as_ref(),
 						*target,
 						slime_transform.translation,
 					);
-
-					for (value, duration) in tower.modifiers.apply_armor.iter() {
-						slime.modifiers.armor.insert(*value, *duration);
-					}
-
-					for (value, duration) in tower.modifiers.apply_poison.iter() {
-						slime.modifiers.poison.insert(*value, *duration);
-					}
-
-					for (value, duration) in tower.modifiers.apply_speed.iter() {
-						slime.modifiers.speed.insert(*value, *duration);
-					}
-
 					commands.spawn_bundle(projectile_bundle);
-				}
-			}
-		}
-	}
-}
-fn towers_cleave(
-	towers: Query<&Tower, Without<TemporaryTower>>,
-	mut slimes: Query<(&Transform, &mut Slime)>,
-) {
-	for tower in towers.iter() {
-		for target in tower.targets.iter() {
-			if tower.cooldown == Duration::ZERO {
-				if let Ok((slime_transform, _)) = slimes.get(*target) {
-					if let Some(cleave) = &tower.modifiers.cleave {
-						for (st, mut s) in slimes.iter_mut() {
-							if flat_distance(*st, *slime_transform) <= cleave.range {
-								s.take_pure_damage((tower.damage * cleave.damage) as usize);
-							}
-						}
-					}
+					tower.stats.shots += 1;
 				}
 			}
 		}
@@ -100,12 +77,25 @@ pub struct TowersAIPlugin;
 
 impl Plugin for TowersAIPlugin {
 	fn build(&self, app: &mut App) {
-		app.add_system_set(
-			SystemSet::on_update(AppState::Enemies)
-				.with_system(towers_targeting)
-				.with_system(towers_cleave.label("tower_cleave"))
-				.with_system(towers_shoot.after("tower_cleave"))
-				.with_system(towers_cooldown),
+		app.add_system_set_to_stage(
+			SimulationStage,
+			SystemSet::new()
+				.with_run_criteria(enemies_phase)
+				.with_system(
+					towers_cooldown
+						.label(SimulationLabel::Cooldown)
+						.after(SimulationLabel::Movement),
+				)
+				.with_system(
+					towers_targeting
+						.label(SimulationLabel::Targeting)
+						.after(SimulationLabel::Cooldown),
+				)
+				.with_system(
+					towers_shoot
+						.label(SimulationLabel::Shoot)
+						.after(SimulationLabel::Targeting),
+				),
 		);
 	}
 }
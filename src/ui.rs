@@ -1,4 +1,7 @@
-use crate::{position_to_transform, Slime, Tower, GRID_SIZE};
+use crate::{
+	count_identical, AppState, Game, GameReport, GameRng, GridGeometry, QualityChances, Recipes,
+	Slime, TemporaryTower, TimeScale, Tower,
+};
 use bevy::prelude::*;
 
 const FONT_SIZE: f32 = 20.0;
@@ -12,6 +15,10 @@ pub struct GameTooltip;
 #[derive(Component)]
 pub struct UiRoot;
 
+/// The text of the game over screen, its third section lists the towers.
+#[derive(Component)]
+pub struct GameReportText;
+
 pub struct UpdateTowerTooltipEvent {
 	pub position: (usize, usize),
 }
@@ -56,11 +63,14 @@ fn update_range_indicator_visibility(
 	}
 }
 
-fn setup_range_indicators(mut commands: Commands, asset_server: Res<AssetServer>) {
-	let size = GRID_SIZE as usize;
-	for y in 0..size {
-		for x in 0..size {
-			let transform = position_to_transform(x as f32, y as f32);
+fn setup_range_indicators(
+	mut commands: Commands,
+	asset_server: Res<AssetServer>,
+	geometry: Res<GridGeometry>,
+) {
+	for y in 0..geometry.height {
+		for x in 0..geometry.width {
+			let transform = geometry.position_to_transform(x as f32, y as f32);
 			commands
 				.spawn_bundle(SpriteBundle {
 					sprite: Sprite {
@@ -86,11 +96,16 @@ fn update_tower_tooltip_handler(
 		for tower in towers.iter() {
 			if tower.position == event.position {
 				let mut text = tower_tooltips.get_single_mut().unwrap();
-				text.sections[1].value = format!("{:?}", tower.kind);
-				text.sections[3].value = format!("{:?}", tower.range);
-				text.sections[5].value = format!("{:?}", tower.damage);
-				text.sections[7].value = format!("{:?}", tower.get_attack_speed());
-				text.sections[9].value = tower.tooltip.to_string();
+				text.sections[1].value = format!("{:?} {:?}", tower.quality, tower.kind);
+				text.sections[3].value = match tower.experience_to_level() {
+					Some(needed) => format!("{} ({}/{} XP)", tower.level, tower.experience, needed),
+					None => format!("{} (max)", tower.level),
+				};
+				text.sections[5].value = format!("{:?}", tower.range);
+				text.sections[7].value = format!("{:?} ({:?})", tower.damage, tower.attack_type);
+				text.sections[9].value = format!("{:?}", tower.get_attack_speed());
+				text.sections[11].value = tower.tooltip.to_string();
+				text.sections[13].value = tower.stats.summary();
 			}
 		}
 	}
@@ -103,17 +118,142 @@ fn update_game_tooltip_handler(
 	for event in update_tooltip.iter() {
 		let mut text = game_tooltips.get_single_mut().unwrap();
 		text.sections[1].value = format!("{:?}", event.lives);
-		text.sections[3].value = format!("{:?}", event.level);
+		text.sections[5].value = format!("{:?}", event.level);
 		if let Some(slime) = &event.slime {
-			text.sections[5].value = format!("{:?}", slime.max_life);
-			text.sections[7].value = format!("{:?}", slime.armor);
-			text.sections[9].value = format!("{:?}", slime.magic_resistance);
-			text.sections[11].value = format!("{:?}", slime.speed);
+			text.sections[7].value = format!("{:?}", slime.max_life);
+			text.sections[9].value = format!("{:?} ({:?})", slime.armor, slime.armor_type);
+			text.sections[11].value = format!("{:?}", slime.magic_resistance);
+			text.sections[13].value = format!("{:?}", slime.speed);
+		}
+	}
+}
+
+fn update_time_scale_ui(
+	time_scale: Res<TimeScale>,
+	app_state: Res<State<AppState>>,
+	mut game_tooltips: Query<&mut Text, With<GameTooltip>>,
+) {
+	if time_scale.is_changed() || app_state.is_changed() {
+		if let Ok(mut text) = game_tooltips.get_single_mut() {
+			text.sections[17].value = if *app_state.current() == AppState::Paused {
+				"Paused".to_string()
+			} else {
+				format!("x{}", time_scale.0)
+			};
+		}
+	}
+}
+
+fn update_gold_ui(game: Res<Game>, mut game_tooltips: Query<&mut Text, With<GameTooltip>>) {
+	if !game.is_changed() {
+		return;
+	}
+	if let Ok(mut text) = game_tooltips.get_single_mut() {
+		text.sections[3].value = format!("{}", game.gold);
+	}
+}
+
+fn update_chance_ui(
+	game: Res<Game>,
+	chances: Res<QualityChances>,
+	mut game_tooltips: Query<&mut Text, With<GameTooltip>>,
+) {
+	if !game.is_changed() {
+		return;
+	}
+	if let Ok(mut text) = game_tooltips.get_single_mut() {
+		text.sections[19].value = match chances.get(game.chance_tier + 1) {
+			Some(next) => format!("{} (U: {} gold)", game.chance_tier, next.cost),
+			None => format!("{} (max)", game.chance_tier),
+		};
+		if let Some(tier) = chances.get(game.chance_tier) {
+			text.sections[21].value = tier
+				.odds
+				.iter()
+				.map(|(quality, chance)| format!("{:?} {}%", quality, chance))
+				.collect::<Vec<String>>()
+				.join("\n");
+		}
+	}
+}
+
+/// Lists the candidates that can be combined, once per kind and quality.
+fn update_combine_ui(
+	towers: Query<&Tower, With<TemporaryTower>>,
+	mut game_tooltips: Query<&mut Text, With<GameTooltip>>,
+) {
+	let mut groups: Vec<String> = Vec::new();
+	for tower in towers.iter() {
+		let identical = count_identical(towers.iter(), tower);
+		let group = format!("{:?} {:?} x{}", tower.quality, tower.kind, identical);
+		if identical > 1 && tower.quality.upgraded(1).is_some() && !groups.contains(&group) {
+			groups.push(group);
+		}
+	}
+	groups.sort();
+	let value = if groups.is_empty() {
+		"-".to_string()
+	} else {
+		groups.join("\n")
+	};
+
+	if let Ok(mut text) = game_tooltips.get_single_mut() {
+		if text.sections[23].value != value {
+			text.sections[23].value = value;
+		}
+	}
+}
+
+/// Lists the recipes one of the candidates can be built into.
+fn update_recipe_ui(
+	recipes: Res<Recipes>,
+	towers: Query<&Tower, With<TemporaryTower>>,
+	placed: Query<&Tower, Without<TemporaryTower>>,
+	mut game_tooltips: Query<&mut Text, With<GameTooltip>>,
+) {
+	let candidates: Vec<&Tower> = towers.iter().collect();
+	let placed: Vec<&Tower> = placed.iter().collect();
+	let mut available: Vec<String> = Vec::new();
+	for tower in candidates.iter() {
+		if let Some((recipe, _)) = recipes.find(tower, &candidates, &placed) {
+			let line = format!(
+				"{:?} {:?} from {:?}",
+				recipe.quality, recipe.kind, tower.kind
+			);
+			if !available.contains(&line) {
+				available.push(line);
+			}
+		}
+	}
+	let value = if available.is_empty() {
+		"-".to_string()
+	} else {
+		available.join("\n")
+	};
+
+	if let Ok(mut text) = game_tooltips.get_single_mut() {
+		if text.sections[25].value != value {
+			text.sections[25].value = value;
+		}
+	}
+}
+
+fn update_report_ui(
+	report: Res<GameReport>,
+	mut texts: Query<(&mut Text, Added<GameReportText>), With<GameReportText>>,
+) {
+	for (mut text, added) in texts.iter_mut() {
+		if added || report.is_changed() {
+			text.sections[2].value = format!(
+				"Towers by {:?}:\n{}",
+				report.sort,
+				report.lines().join("\n")
+			);
 		}
 	}
 }
 
-fn setup_tooltip(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn setup_tooltip(mut commands: Commands, asset_server: Res<AssetServer>, rng: Res<GameRng>) {
 	let font = asset_server.load("FiraSans-Bold.ttf");
 
 	commands
@@ -152,6 +292,22 @@ fn setup_tooltip(mut commands: Commands, asset_server: Res<AssetServer>) {
 									color: Color::GOLD,
 								},
 							},
+							TextSection {
+								value: "\nLevel: ".to_string(),
+								style: TextStyle {
+									font: font.clone(),
+									font_size: FONT_SIZE,
+									color: Color::WHITE,
+								},
+							},
+							TextSection {
+								value: "".to_string(),
+								style: TextStyle {
+									font: font.clone(),
+									font_size: FONT_SIZE,
+									color: Color::GOLD,
+								},
+							},
 							TextSection {
 								value: "\nRange:".to_string(),
 								style: TextStyle {
@@ -216,6 +372,22 @@ fn setup_tooltip(mut commands: Commands, asset_server: Res<AssetServer>) {
 									color: Color::GOLD,
 								},
 							},
+							TextSection {
+								value: "\nStats:\n".to_string(),
+								style: TextStyle {
+									font: font.clone(),
+									font_size: FONT_SIZE,
+									color: Color::WHITE,
+								},
+							},
+							TextSection {
+								value: "".to_string(),
+								style: TextStyle {
+									font: font.clone(),
+									font_size: FONT_SIZE,
+									color: Color::GOLD,
+								},
+							},
 						],
 						..Default::default()
 					},
@@ -247,6 +419,22 @@ fn setup_tooltip(mut commands: Commands, asset_server: Res<AssetServer>) {
 									color: Color::GOLD,
 								},
 							},
+							TextSection {
+								value: "\nGold: ".to_string(),
+								style: TextStyle {
+									font: font.clone(),
+									font_size: FONT_SIZE,
+									color: Color::WHITE,
+								},
+							},
+							TextSection {
+								value: "".to_string(),
+								style: TextStyle {
+									font: font.clone(),
+									font_size: FONT_SIZE,
+									color: Color::GOLD,
+								},
+							},
 							TextSection {
 								value: "\n\nLevel: ".to_string(),
 								style: TextStyle {
@@ -327,6 +515,102 @@ fn setup_tooltip(mut commands: Commands, asset_server: Res<AssetServer>) {
 									color: Color::GOLD,
 								},
 							},
+							TextSection {
+								value: "\n\nSeed: ".to_string(),
+								style: TextStyle {
+									font: font.clone(),
+									font_size: FONT_SIZE,
+									color: Color::WHITE,
+								},
+							},
+							TextSection {
+								value: rng.seed.to_string(),
+								style: TextStyle {
+									font: font.clone(),
+									font_size: FONT_SIZE,
+									color: Color::GOLD,
+								},
+							},
+							TextSection {
+								value: "\nGame speed: ".to_string(),
+								style: TextStyle {
+									font: font.clone(),
+									font_size: FONT_SIZE,
+									color: Color::WHITE,
+								},
+							},
+							TextSection {
+								value: "x1".to_string(),
+								style: TextStyle {
+									font: font.clone(),
+									font_size: FONT_SIZE,
+									color: Color::GOLD,
+								},
+							},
+							TextSection {
+								value: "\n\nChance level: ".to_string(),
+								style: TextStyle {
+									font: font.clone(),
+									font_size: FONT_SIZE,
+									color: Color::WHITE,
+								},
+							},
+							TextSection {
+								value: "".to_string(),
+								style: TextStyle {
+									font: font.clone(),
+									font_size: FONT_SIZE,
+									color: Color::GOLD,
+								},
+							},
+							TextSection {
+								value: "\nOdds:\n".to_string(),
+								style: TextStyle {
+									font: font.clone(),
+									font_size: FONT_SIZE,
+									color: Color::WHITE,
+								},
+							},
+							TextSection {
+								value: "".to_string(),
+								style: TextStyle {
+									font: font.clone(),
+									font_size: FONT_SIZE,
+									color: Color::GOLD,
+								},
+							},
+							TextSection {
+								value: "\nCombine (C):\n".to_string(),
+								style: TextStyle {
+									font: font.clone(),
+									font_size: FONT_SIZE,
+									color: Color::WHITE,
+								},
+							},
+							TextSection {
+								value: "-".to_string(),
+								style: TextStyle {
+									font: font.clone(),
+									font_size: FONT_SIZE,
+									color: Color::GOLD,
+								},
+							},
+							TextSection {
+								value: "\nRecipes (R):\n".to_string(),
+								style: TextStyle {
+									font: font.clone(),
+									font_size: FONT_SIZE,
+									color: Color::WHITE,
+								},
+							},
+							TextSection {
+								value: "-".to_string(),
+								style: TextStyle {
+									font: font.clone(),
+									font_size: FONT_SIZE,
+									color: Color::GOLD,
+								},
+							},
 						],
 						..Default::default()
 					},
@@ -340,14 +624,18 @@ pub struct UiPlugin;
 
 impl Plugin for UiPlugin {
 	fn build(&self, app: &mut App) {
-		app.add_event::<UpdateTowerTooltipEvent>()
-			.add_event::<UpdateGameTooltipEvent>()
-			.add_event::<UpdateRangeIndicatorScaleEvent>()
+		app.add_event::<UpdateRangeIndicatorScaleEvent>()
 			.add_startup_system(setup_tooltip)
 			.add_startup_system(setup_range_indicators)
 			.add_system(update_game_tooltip_handler)
+			.add_system(update_time_scale_ui)
+			.add_system(update_gold_ui)
+			.add_system(update_chance_ui)
+			.add_system(update_combine_ui)
+			.add_system(update_recipe_ui)
 			.add_system(update_tower_tooltip_handler)
 			.add_system(update_range_indicator_visibility)
-			.add_system(update_range_indicator_scale);
+			.add_system(update_range_indicator_scale)
+			.add_system_set(SystemSet::on_update(AppState::GameOver).with_system(update_report_ui));
 	}
 }
@@ -1,46 +1,86 @@
-use crate::{GRID_SIZE, TILE_SIZE, TILE_SPACER};
+use crate::{TILE_SIZE, TILE_SPACER};
 use bevy::prelude::*;
+use std::env;
+use std::path::PathBuf;
 
-pub fn vec2_to_position(vec: Vec2) -> (f32, f32) {
-    let (mut x, mut y) = (
-        ((vec.x + (GRID_SIZE * TILE_SIZE - TILE_SIZE + TILE_SPACER) / 2.0)
-            / (TILE_SIZE + TILE_SPACER))
-            .round(),
-        (((vec.y * -1.0) + ((GRID_SIZE * TILE_SIZE - TILE_SIZE) / 2.0) + (TILE_SPACER * 1.5))
-            / (TILE_SIZE + TILE_SPACER))
-            .round(),
-    );
-    if x == -0.0 {
-        x = 0.0
+/// Path of a file in the assets folder, found the same way as the `AssetServer` does.
+pub fn asset_path(name: &str) -> PathBuf {
+    let root = env::var("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| {
+            env::current_exe()
+                .ok()
+                .and_then(|exe| exe.parent().map(PathBuf::from))
+        })
+        .unwrap_or_default();
+    root.join("assets").join(name)
+}
+
+/// Size of the board and of its tiles, to go from grid positions to the screen and back.
+pub struct GridGeometry {
+    pub width: usize,
+    pub height: usize,
+    pub tile_size: f32,
+    pub tile_spacer: f32,
+}
+
+impl GridGeometry {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            tile_size: TILE_SIZE,
+            tile_spacer: TILE_SPACER,
+        }
     }
-    if y == -0.0 {
-        y = 0.0
+
+    pub fn vec2_to_position(&self, vec: Vec2) -> (f32, f32) {
+        let (width, height) = (self.width as f32, self.height as f32);
+        let (tile, spacer) = (self.tile_size, self.tile_spacer);
+        let (mut x, mut y) = (
+            ((vec.x + (width * tile - tile + spacer) / 2.0) / (tile + spacer)).round(),
+            (((vec.y * -1.0) + ((height * tile - tile) / 2.0) + (spacer * 1.5)) / (tile + spacer))
+                .round(),
+        );
+        if x == -0.0 {
+            x = 0.0
+        }
+        if y == -0.0 {
+            y = 0.0
+        }
+        (x, y)
     }
-    (x, y)
-}
 
-pub fn fit_to_grid(pos: (f32, f32)) -> Option<(usize, usize)> {
-    if 0.0 > pos.0 || 0.0 > pos.1 || pos.0 >= GRID_SIZE || pos.1 >= GRID_SIZE {
-        None
-    } else {
-        Some((pos.0 as usize, pos.1 as usize))
+    pub fn fit_to_grid(&self, pos: (f32, f32)) -> Option<(usize, usize)> {
+        if 0.0 > pos.0 || 0.0 > pos.1 || pos.0 >= self.width as f32 || pos.1 >= self.height as f32 {
+            None
+        } else {
+            Some((pos.0 as usize, pos.1 as usize))
+        }
     }
-}
 
-pub fn position_to_translation(x: f32, y: f32) -> Vec3 {
-    Vec3::new(
-        x * TILE_SIZE - (GRID_SIZE * TILE_SIZE / 2.0) + (0.5 * TILE_SIZE) + x * TILE_SPACER
-            - TILE_SPACER * 0.5,
-        -1.0 * (y * TILE_SIZE - (GRID_SIZE * TILE_SIZE / 2.0)
-            + (0.5 * TILE_SIZE)
-            + y * TILE_SPACER
-            - TILE_SPACER * 1.5),
-        1.0,
-    )
-}
+    pub fn position_to_translation(&self, x: f32, y: f32) -> Vec3 {
+        let (width, height) = (self.width as f32, self.height as f32);
+        let (tile, spacer) = (self.tile_size, self.tile_spacer);
+        Vec3::new(
+            x * tile - (width * tile / 2.0) + (0.5 * tile) + x * spacer - spacer * 0.5,
+            -1.0 * (y * tile - (height * tile / 2.0) + (0.5 * tile) + y * spacer - spacer * 1.5),
+            1.0,
+        )
+    }
 
-pub fn position_to_transform(x: f32, y: f32) -> Transform {
-    Transform::from_translation(position_to_translation(x, y))
+    pub fn position_to_transform(&self, x: f32, y: f32) -> Transform {
+        Transform::from_translation(self.position_to_translation(x, y))
+    }
+
+    /// Camera scale showing the whole board in the middle of the window, next to the tooltips.
+    pub fn fit_scale(&self, window_width: f32, window_height: f32) -> f32 {
+        let step = self.tile_size + self.tile_spacer;
+        let width = (self.width as f32 * step) / (window_width * 0.55);
+        let height = (self.height as f32 * step) / window_height;
+        width.max(height).max(1.0)
+    }
 }
 
 pub fn flat_distance(t1: Transform, t2: Transform) -> f32 {